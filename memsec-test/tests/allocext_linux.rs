@@ -1,14 +1,15 @@
 #![cfg(feature = "alloc_ext")]
 #![cfg(target_os = "linux")]
 
+use std::os::unix::io::{AsFd, AsRawFd, FromRawFd, OwnedFd};
 use std::ptr::NonNull;
 
 #[test]
 fn memfd_secret_u64_test() {
     unsafe {
         let mut p: NonNull<u64> = memsec::memfd_secret().unwrap();
-        *p.as_mut() = std::u64::MAX;
-        assert_eq!(*p.as_ref(), std::u64::MAX);
+        *p.as_mut() = u64::MAX;
+        assert_eq!(*p.as_ref(), u64::MAX);
         memsec::free_memfd_secret(p);
     }
 }
@@ -84,12 +85,311 @@ fn memfd_secret_mprotect_1_test() {
     }
 }
 
+#[test]
+fn secret_malloc_backend_test() {
+    unsafe {
+        let (mut p, backend): (NonNull<u64>, _) = memsec::secret_malloc().unwrap();
+        let expected = if memsec::memfd_secret_supported() {
+            memsec::Backend::MemfdSecret
+        } else {
+            memsec::Backend::Malloc
+        };
+        assert_eq!(backend, expected);
+
+        *p.as_mut() = u64::MAX;
+        assert_eq!(*p.as_ref(), u64::MAX);
+        memsec::secret_free(p);
+    }
+}
+
+#[test]
+fn memfd_secret_fd_test() {
+    use std::os::unix::io::AsRawFd;
+
+    unsafe fn cloexec<T: ?Sized>(p: NonNull<T>) -> bool {
+        let fd = memsec::memfd_secret_as_fd(p).as_raw_fd();
+        let flags = libc::fcntl(fd, libc::F_GETFD);
+        assert!(flags >= 0);
+        flags & libc::FD_CLOEXEC != 0
+    }
+
+    unsafe {
+        let p = memsec::memfd_secret_sized(16).unwrap();
+        assert!(cloexec(p));
+        memsec::free_memfd_secret(p);
+
+        let opts = memsec::AllocOptions::new().cloexec(false);
+        let p = memsec::try_memfd_secret_sized_with(16, opts).unwrap();
+        assert!(!cloexec(p));
+        memsec::free_memfd_secret(p);
+
+        assert_eq!(
+            memsec::try_memfd_secret_sized_with(usize::MAX - 1, opts),
+            Err(memsec::Error::TooLarge)
+        );
+    }
+}
+
+#[test]
+fn secret_arena_test() {
+    let arena = match memsec::SecretArena::new() {
+        Ok(arena) => arena,
+        Err(_) => return,
+    };
+
+    unsafe {
+        let mut ptrs: Vec<NonNull<[u8]>> = (0..64)
+            .map(|i| {
+                let mut p = arena.malloc_sized(100 + i * 100).unwrap();
+                memsec::memset(p.as_mut().as_mut_ptr(), i as u8, p.as_ref().len());
+                p
+            })
+            .collect();
+
+        // free every other allocation, and reuse the space
+        for p in ptrs.iter().step_by(2) {
+            arena.free(*p);
+        }
+        for i in (0..64).step_by(2) {
+            let mut p = arena.malloc_sized(100 + i * 100).unwrap();
+            memsec::memset(p.as_mut().as_mut_ptr(), i as u8, p.as_ref().len());
+            ptrs[i] = p;
+        }
+
+        for (i, p) in ptrs.into_iter().enumerate() {
+            assert!(p.as_ref().iter().all(|&b| b == i as u8));
+            arena.free(p);
+        }
+
+        let mut p: NonNull<u64> = arena.malloc().unwrap();
+        *p.as_mut() = u64::MAX;
+        assert!(memsec::mprotect(p, memsec::Prot::ReadOnly));
+        assert_eq!(*p.as_ref(), u64::MAX);
+        assert!(memsec::mprotect(p, memsec::Prot::ReadWrite));
+        arena.free(p);
+    }
+}
+
+#[test]
+fn secret_arena_drop_test() {
+    if !memsec::memfd_secret_supported() {
+        return;
+    }
+
+    // counters are process-wide, keep other tests out
+    let handle = procspawn::spawn((), |()| unsafe {
+        let page_size = memsec::page_size();
+        let arena = memsec::SecretArena::with_capacity(page_size * 2).unwrap();
+        let mut p: NonNull<[u8]> = arena.malloc_sized(100).unwrap();
+        p.as_mut().fill(0x2a);
+        let q: NonNull<[u8]> = arena.malloc_sized(100).unwrap();
+        assert!(memsec::mprotect(q, memsec::Prot::NoAccess));
+        let file = arena.as_fd().try_clone_to_owned().unwrap();
+
+        // both allocations are still alive
+        drop(arena);
+        assert_eq!(memsec::stats().memfd_secret.live_allocations, 0);
+
+        let data = libc::mmap(
+            std::ptr::null_mut(),
+            page_size * 2,
+            libc::PROT_READ,
+            libc::MAP_SHARED,
+            file.as_raw_fd(),
+            0,
+        );
+        assert_ne!(data, libc::MAP_FAILED);
+        let wiped = std::slice::from_raw_parts(data as *const u8, page_size * 2)
+            .iter()
+            .all(|&b| b == 0);
+        libc::munmap(data, page_size * 2);
+        wiped
+    });
+
+    assert!(handle.join().unwrap());
+}
+
+#[test]
+fn secret_arena_guard_page_test() {
+    if !memsec::memfd_secret_supported() {
+        return;
+    }
+
+    let handle = procspawn::spawn((), |()| unsafe {
+        let arena = memsec::SecretArena::new().unwrap();
+        let p: NonNull<[u8; 16]> = arena.malloc().unwrap();
+        let page_size = libc::sysconf(libc::_SC_PAGESIZE) as usize;
+        *(p.as_ptr() as *mut u8).add(page_size) = 0x01;
+    });
+
+    assert!(handle.join().is_err());
+}
+
+fn socketpair() -> (OwnedFd, OwnedFd) {
+    let mut fds = [0; 2];
+    assert_eq!(
+        unsafe { libc::socketpair(libc::AF_UNIX, libc::SOCK_STREAM, 0, fds.as_mut_ptr()) },
+        0
+    );
+    unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) }
+}
+
+#[test]
+fn send_recv_secret_test() {
+    if !memsec::memfd_secret_supported() {
+        return;
+    }
+
+    let (tx, rx) = socketpair();
+
+    unsafe {
+        let mut p = memsec::memfd_secret_sized(100).unwrap();
+        p.as_mut().copy_from_slice(&[0x2a; 100]);
+        memsec::send_secret(tx.as_fd(), p).unwrap();
+
+        let opts = memsec::AllocOptions::new().label("received-key");
+        let mut q = memsec::recv_secret_with(rx.as_fd(), opts).unwrap();
+        assert_eq!(q.as_ref(), &[0x2a; 100][..]);
+        assert_eq!(memsec::label(q), Some("received-key"));
+        assert_ne!(q.as_ptr() as *mut u8, p.as_ptr() as *mut u8);
+
+        // the data pages are shared
+        q.as_mut()[0] = 0x01;
+        assert_eq!(p.as_ref()[0], 0x01);
+
+        // the receiver doesn't wipe the data
+        assert!(memsec::mprotect(q, memsec::Prot::ReadOnly));
+        memsec::free_memfd_secret(q);
+        assert_eq!(p.as_ref()[1..], [0x2a; 99][..]);
+
+        // arena allocations and plain allocations can't be sent
+        let arena = memsec::SecretArena::new().unwrap();
+        let a = arena.malloc_sized(16).unwrap();
+        assert_eq!(
+            memsec::send_secret(tx.as_fd(), a),
+            Err(memsec::Error::Os(libc::EINVAL))
+        );
+        arena.free(a);
+
+        let m = memsec::malloc_sized(16).unwrap();
+        assert!(memsec::send_secret(tx.as_fd(), m).is_err());
+        memsec::free(m);
+
+        memsec::free_memfd_secret(p);
+    }
+}
+
+#[test]
+fn send_recv_secret_sender_free_test() {
+    if !memsec::memfd_secret_supported() {
+        return;
+    }
+
+    // a canary failure aborts, keep it out of the test process
+    let handle = procspawn::spawn((), |()| unsafe {
+        let (tx, rx) = socketpair();
+        let mut p = memsec::memfd_secret_sized(100).unwrap();
+        p.as_mut().fill(0x2a);
+        memsec::send_secret(tx.as_fd(), p).unwrap();
+        let q = memsec::recv_secret(rx.as_fd()).unwrap();
+
+        // the file only holds the data pages, the header stays private
+        let mut stat: libc::stat = std::mem::zeroed();
+        let fd = memsec::memfd_secret_as_fd(q).as_raw_fd();
+        assert_eq!(libc::fstat(fd, &mut stat), 0);
+        assert_eq!(stat.st_size as usize, memsec::page_size());
+
+        // the sender frees first, e.g. when rotating the key
+        memsec::free_memfd_secret(p);
+        let wiped = q.as_ref().iter().all(|&b| b == 0);
+        memsec::free_memfd_secret(q);
+        wiped
+    });
+
+    assert!(handle.join().unwrap());
+}
+
+#[test]
+fn send_recv_secret_fork_test() {
+    if !memsec::memfd_secret_supported() {
+        return;
+    }
+
+    // the forked child allocates, so fork from a single threaded process
+    let handle = procspawn::spawn((), |()| unsafe {
+        let (tx, rx) = socketpair();
+        let mut p = memsec::memfd_secret_sized(4096).unwrap();
+        p.as_mut().fill(0x2a);
+
+        let pid = libc::fork();
+        if pid == 0 {
+            drop(tx);
+            let ok = match memsec::recv_secret(rx.as_fd()) {
+                Ok(q) => {
+                    let ok = q.as_ref().iter().all(|&b| b == 0x2a);
+                    memsec::free_memfd_secret(q);
+                    ok
+                }
+                Err(_) => false,
+            };
+            libc::_exit(if ok { 0 } else { 1 });
+        }
+
+        assert!(pid > 0);
+        memsec::send_secret(tx.as_fd(), p).unwrap();
+
+        let mut status = 0;
+        assert_eq!(libc::waitpid(pid, &mut status, 0), pid);
+        memsec::free_memfd_secret(p);
+        libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0
+    });
+
+    assert!(handle.join().unwrap());
+}
+
+#[test]
+fn secret_file_test() {
+    use std::os::unix::io::AsRawFd;
+    use std::process::Command;
+
+    let file = memsec::SecretFile::new(b"hunter2").unwrap();
+    assert_eq!(file.len(), 7);
+    assert_eq!(std::fs::read(file.path()).unwrap(), b"hunter2");
+
+    // sealed against writes and resizes
+    assert!(std::fs::OpenOptions::new()
+        .write(true)
+        .open(file.path())
+        .and_then(|mut f| std::io::Write::write_all(&mut f, b"x"))
+        .is_err());
+    let seals = unsafe { libc::fcntl(file.as_fd().as_raw_fd(), libc::F_GET_SEALS) };
+    assert_ne!(seals & libc::F_SEAL_GROW, 0);
+    assert_ne!(seals & libc::F_SEAL_SHRINK, 0);
+    assert_eq!(
+        unsafe { libc::ftruncate(file.as_fd().as_raw_fd(), 0) },
+        -1
+    );
+
+    // children only see the file once it is inheritable
+    let cat = || Command::new("cat").arg(file.path()).output().unwrap();
+    assert!(!cat().status.success());
+    file.set_inheritable(true).unwrap();
+    let output = cat();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"hunter2");
+
+    let empty = memsec::SecretFile::new(&[]).unwrap();
+    assert!(empty.is_empty());
+    assert_eq!(std::fs::read(empty.path()).unwrap(), b"");
+}
+
 procspawn::enable_test_support!();
 
 use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 enum Offset {
     AddOffset(usize),
@@ -0,0 +1,78 @@
+#![cfg(feature = "alloc")]
+#![cfg(unix)]
+
+mod common;
+
+use common::{faulted, fork_with};
+use std::ptr::NonNull;
+
+procspawn::enable_test_support!();
+
+// `install_atfork` is process wide, every test runs in its own process.
+
+#[test]
+fn atfork_noaccess_test() {
+    let handle = procspawn::spawn((), |()| unsafe {
+        assert!(memsec::install_atfork(memsec::AtforkPolicy::NoAccess));
+
+        let mut p: NonNull<[u8; 16]> = memsec::malloc().unwrap();
+        let opts = memsec::AllocOptions::new().inheritable(true);
+        let mut q: NonNull<[u8; 16]> = memsec::malloc_with(opts).unwrap();
+        *p.as_mut() = [0x42; 16];
+        *q.as_mut() = [0x43; 16];
+
+        // inheritable allocations are kept
+        let status = fork_with(|| (*q.as_ref() != [0x43; 16]) as i32);
+        assert!(libc::WIFEXITED(status));
+        assert_eq!(libc::WEXITSTATUS(status), 0);
+
+        // the others fault
+        let status = fork_with(|| std::ptr::read_volatile(p.as_ptr() as *const u8) as i32);
+        assert!(faulted(status));
+
+        // the parent is untouched
+        assert_eq!(*p.as_ref(), [0x42; 16]);
+        memsec::free(p);
+        memsec::free(q);
+    });
+
+    handle.join().unwrap();
+}
+
+#[test]
+fn atfork_free_test() {
+    let handle = procspawn::spawn((), |()| unsafe {
+        assert!(memsec::install_atfork(memsec::AtforkPolicy::Free));
+
+        let mut p: NonNull<[u8; 16]> = memsec::malloc().unwrap();
+        *p.as_mut() = [0x42; 16];
+        let locked = memsec::locked_bytes();
+
+        // the child unlocks and frees `p`
+        let status = fork_with(|| (memsec::locked_bytes() >= locked) as i32);
+        assert!(libc::WIFEXITED(status));
+        assert_eq!(libc::WEXITSTATUS(status), 0);
+
+        assert_eq!(*p.as_ref(), [0x42; 16]);
+        assert_eq!(memsec::locked_bytes(), locked);
+        memsec::free(p);
+    });
+
+    handle.join().unwrap();
+}
+
+#[test]
+fn atfork_before_install_test() {
+    let handle = procspawn::spawn((), |()| unsafe {
+        let mut p: NonNull<[u8; 16]> = memsec::malloc().unwrap();
+        *p.as_mut() = [0x42; 16];
+        assert!(memsec::install_atfork(memsec::AtforkPolicy::NoAccess));
+
+        // allocated before the handler, still covered
+        let status = fork_with(|| std::ptr::read_volatile(p.as_ptr() as *const u8) as i32);
+        assert!(faulted(status));
+        memsec::free(p);
+    });
+
+    handle.join().unwrap();
+}
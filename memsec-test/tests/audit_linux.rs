@@ -0,0 +1,41 @@
+#![cfg(feature = "alloc")]
+#![cfg(feature = "use_os")]
+#![cfg(target_os = "linux")]
+
+use std::ptr::NonNull;
+
+#[test]
+fn audit_malloc_test() {
+    unsafe {
+        let opts = memsec::AllocOptions::new().label("audit");
+        let p: NonNull<[u8; 100]> = memsec::malloc_with(opts).unwrap();
+
+        let audit = memsec::audit(p).unwrap();
+        assert_eq!(audit.label, Some("audit"));
+        assert_eq!(audit.size % 4096, 0);
+        assert!(audit.dontdump);
+        assert!(!audit.wipeonfork);
+        assert_eq!(audit.guard_pages, [memsec::Prot::NoAccess; 2]);
+
+        // `mlock` may fail under a small `RLIMIT_MEMLOCK`
+        if matches!(memsec::memlock_limit(), Some((soft, _)) if soft >= 1 << 20) {
+            assert_eq!(audit.locked, audit.size);
+        }
+
+        memsec::free(p);
+    }
+}
+
+#[test]
+fn audit_wipeonfork_test() {
+    unsafe {
+        let opts = memsec::AllocOptions::new().fork(memsec::ForkPolicy::Wipe);
+        let p: NonNull<[u8; 16]> = memsec::malloc_with(opts).unwrap();
+
+        let audit = memsec::audit(p).unwrap();
+        assert!(audit.wipeonfork);
+        assert_eq!(audit.label, None);
+
+        memsec::free(p);
+    }
+}
@@ -0,0 +1,22 @@
+//! Helpers shared by the test crates that fork.
+
+#![allow(dead_code)]
+
+/// Fork, run `f` in the child and return the child's wait status.
+pub unsafe fn fork_with<F: FnOnce() -> i32>(f: F) -> libc::c_int {
+    match libc::fork() {
+        -1 => panic!("fork failed"),
+        0 => libc::_exit(f()),
+        pid => {
+            let mut status = 0;
+            assert_eq!(libc::waitpid(pid, &mut status, 0), pid);
+            status
+        }
+    }
+}
+
+/// Whether the child died accessing a `Prot::NoAccess` page, macOS raises `SIGBUS`.
+pub fn faulted(status: libc::c_int) -> bool {
+    libc::WIFSIGNALED(status)
+        && (libc::WTERMSIG(status) == libc::SIGSEGV || libc::WTERMSIG(status) == libc::SIGBUS)
+}
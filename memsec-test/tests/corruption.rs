@@ -0,0 +1,44 @@
+#![cfg(feature = "alloc")]
+
+use std::ptr::NonNull;
+
+procspawn::enable_test_support!();
+
+fn handler(report: &memsec::CorruptionReport<'_>) -> ! {
+    panic!(
+        "{:?} {:p} {} {:?}",
+        report.kind, report.address, report.size, report.label
+    );
+}
+
+#[test]
+fn corruption_canary_test() {
+    let handle = procspawn::spawn((), |()| unsafe {
+        memsec::set_corruption_handler(handler);
+
+        let opts = memsec::AllocOptions::new().label("key");
+        let p: NonNull<[u8]> = memsec::malloc_sized_with(32, opts).unwrap();
+        let memptr = p.as_ptr() as *mut u8;
+        *memptr.sub(1) ^= 1;
+        memsec::free(p);
+    });
+
+    let err = handle.join().unwrap_err();
+    let message = err.panic_info().unwrap().message();
+    assert!(message.starts_with("Canary 0x"), "{}", message);
+    assert!(message.ends_with(" 32 Some(\"key\")"), "{}", message);
+}
+
+#[test]
+fn corruption_pointer_test() {
+    let handle = procspawn::spawn((), |()| unsafe {
+        memsec::set_corruption_handler(handler);
+        assert_eq!(memsec::init(), Ok(()));
+
+        memsec::free(NonNull::new_unchecked(32 as *mut u8));
+    });
+
+    let err = handle.join().unwrap_err();
+    let message = err.panic_info().unwrap().message();
+    assert_eq!(message, "Pointer 0x20 0 None");
+}
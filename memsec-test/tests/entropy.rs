@@ -0,0 +1,108 @@
+#![cfg(feature = "alloc")]
+
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+procspawn::enable_test_support!();
+
+#[test]
+fn entropy_source_test() {
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    fn source(buf: &mut [u8]) -> Result<(), memsec::Error> {
+        CALLS.fetch_add(1, Ordering::Relaxed);
+        buf.fill(0x42);
+        Ok(())
+    }
+
+    // the entropy source can only be set before the first allocation
+    let handle = procspawn::spawn((), |()| unsafe {
+        assert!(memsec::set_entropy_source(source));
+        assert_eq!(memsec::init(), Ok(()));
+        assert!(!memsec::set_entropy_source(source));
+
+        let p: NonNull<u64> = memsec::malloc().unwrap();
+        memsec::free(p);
+
+        CALLS.load(Ordering::Relaxed)
+    });
+
+    assert_eq!(handle.join().unwrap(), 1);
+}
+
+#[test]
+fn entropy_source_failure_test() {
+    fn source(_: &mut [u8]) -> Result<(), memsec::Error> {
+        Err(memsec::Error::Os(libc::EPERM))
+    }
+
+    let handle = procspawn::spawn((), |()| unsafe {
+        assert!(memsec::set_entropy_source(source));
+        assert_eq!(memsec::init(), Err(memsec::Error::Os(libc::EPERM)));
+        memsec::malloc::<u64>().is_none() && memsec::malloc_sized(16).is_none()
+    });
+
+    assert!(handle.join().unwrap());
+}
+
+#[test]
+fn entropy_source_after_init_test() {
+    let handle = procspawn::spawn((), |()| {
+        assert_eq!(memsec::init(), Ok(()));
+        memsec::set_entropy_source(|_| Ok(()))
+    });
+
+    assert!(!handle.join().unwrap());
+}
+
+#[test]
+fn entropy_source_after_page_size_test() {
+    // only allocations need the canary secret
+    let handle = procspawn::spawn((), |()| {
+        assert!(memsec::page_size() > 0);
+        let mut buf = [0u8; 16];
+        drop(memsec::LockedRegion::new(&mut buf));
+        #[cfg(all(feature = "alloc_ext", target_os = "linux"))]
+        drop(memsec::SecretArena::with_capacity(4096));
+        memsec::set_entropy_source(|buf| {
+            buf.fill(0x42);
+            Ok(())
+        })
+    });
+
+    assert!(handle.join().unwrap());
+}
+
+#[test]
+fn entropy_source_panic_test() {
+    // a panic while initializing would leave later calls spinning
+    let handle = procspawn::spawn((), |()| {
+        memsec::set_entropy_source(|_| panic!("no entropy"));
+        let _ = std::panic::catch_unwind(memsec::init);
+        memsec::init().is_ok()
+    });
+
+    let err = handle.join().unwrap_err();
+    assert!(!err.is_panic());
+}
+
+#[cfg(feature = "insecure_deterministic_canary")]
+#[test]
+fn insecure_deterministic_canary_test() {
+    fn canary() -> [u8; 16] {
+        unsafe {
+            assert!(memsec::set_entropy_source(
+                memsec::insecure_deterministic_entropy
+            ));
+            let p: NonNull<u64> = memsec::malloc().unwrap();
+            let canary = *(p.as_ptr() as *const u8).sub(16).cast::<[u8; 16]>();
+            memsec::free(p);
+            canary
+        }
+    }
+
+    // same canary in every process, whatever the addresses
+    let a = procspawn::spawn((), |()| canary()).join().unwrap();
+    let b = procspawn::spawn((), |()| canary()).join().unwrap();
+    assert_eq!(a, b);
+}
@@ -0,0 +1,88 @@
+#![cfg(feature = "alloc")]
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use common::fork_with;
+use std::ptr::NonNull;
+
+#[test]
+fn fork_inherit_test() {
+    unsafe {
+        let mut p: NonNull<[u8; 16]> = memsec::malloc().unwrap();
+        *p.as_mut() = [0x42; 16];
+
+        let status = fork_with(|| (*p.as_ref() != [0x42; 16]) as i32);
+        assert!(libc::WIFEXITED(status));
+        assert_eq!(libc::WEXITSTATUS(status), 0);
+
+        memsec::free(p);
+    }
+}
+
+#[test]
+fn fork_wipe_test() {
+    unsafe {
+        let opts = memsec::AllocOptions::new().fork(memsec::ForkPolicy::Wipe);
+        let mut p: NonNull<[u8; 16]> = memsec::malloc_with(opts).unwrap();
+        *p.as_mut() = [0x42; 16];
+
+        // the child sees zeros
+        let status = fork_with(|| (*p.as_ref() != [0; 16]) as i32);
+        assert!(libc::WIFEXITED(status));
+        assert_eq!(libc::WEXITSTATUS(status), 0);
+
+        assert_eq!(*p.as_ref(), [0x42; 16]);
+        memsec::free(p);
+    }
+}
+
+#[test]
+fn fork_dontfork_test() {
+    unsafe {
+        let opts = memsec::AllocOptions::new().fork(memsec::ForkPolicy::DontFork);
+        let mut p: NonNull<[u8; 16]> = memsec::malloc_with(opts).unwrap();
+        *p.as_mut() = [0x42; 16];
+
+        // the child sees nothing
+        let status = fork_with(|| std::ptr::read_volatile(p.as_ptr() as *const u8) as i32);
+        assert!(libc::WIFSIGNALED(status));
+        assert_eq!(libc::WTERMSIG(status), libc::SIGSEGV);
+
+        assert_eq!(*p.as_ref(), [0x42; 16]);
+        memsec::free(p);
+    }
+}
+
+#[cfg(feature = "alloc_ext")]
+#[test]
+fn fork_secret_malloc_test() {
+    unsafe {
+        // `memfd_secret` pages are shared, wiping falls back to `malloc`
+        let opts = memsec::AllocOptions::new().fork(memsec::ForkPolicy::Wipe);
+        let (mut p, backend): (NonNull<[u8; 16]>, _) = memsec::secret_malloc_with(opts).unwrap();
+        assert_eq!(backend, memsec::Backend::Malloc);
+        *p.as_mut() = [0x42; 16];
+
+        let status = fork_with(|| (*p.as_ref() != [0; 16]) as i32);
+        assert!(libc::WIFEXITED(status));
+        assert_eq!(libc::WEXITSTATUS(status), 0);
+        memsec::secret_free(p);
+
+        if memsec::memfd_secret_supported() {
+            assert_eq!(
+                memsec::try_memfd_secret_with::<[u8; 16]>(opts),
+                Err(memsec::Error::Os(libc::EINVAL))
+            );
+        }
+
+        let opts = memsec::AllocOptions::new().fork(memsec::ForkPolicy::DontFork);
+        let (mut p, _): (NonNull<[u8; 16]>, _) = memsec::secret_malloc_with(opts).unwrap();
+        *p.as_mut() = [0x42; 16];
+
+        let status = fork_with(|| std::ptr::read_volatile(p.as_ptr() as *const u8) as i32);
+        assert!(libc::WIFSIGNALED(status));
+        assert_eq!(libc::WTERMSIG(status), libc::SIGSEGV);
+        memsec::secret_free(p);
+    }
+}
@@ -0,0 +1,24 @@
+#![cfg(feature = "use_os")]
+#![cfg(target_os = "linux")]
+
+procspawn::enable_test_support!();
+
+#[test]
+fn harden_test() {
+    // hardening can't be undone, keep it out of the test process
+    let handle = procspawn::spawn((), |()| unsafe {
+        memsec::harden::apply(true).unwrap();
+
+        let mut limit = std::mem::zeroed::<libc::rlimit>();
+        assert_eq!(libc::getrlimit(libc::RLIMIT_CORE, &mut limit), 0);
+
+        (
+            libc::prctl(libc::PR_GET_DUMPABLE, 0, 0, 0, 0),
+            limit.rlim_cur,
+            limit.rlim_max,
+            libc::prctl(libc::PR_GET_NO_NEW_PRIVS, 0, 0, 0, 0),
+        )
+    });
+
+    assert_eq!(handle.join().unwrap(), (0, 0, 0, 1));
+}
@@ -6,8 +6,8 @@ use std::ptr::NonNull;
 fn malloc_u64_test() {
     unsafe {
         let mut p: NonNull<u64> = memsec::malloc().unwrap();
-        *p.as_mut() = std::u64::MAX;
-        assert_eq!(*p.as_ref(), std::u64::MAX);
+        *p.as_mut() = u64::MAX;
+        assert_eq!(*p.as_ref(), u64::MAX);
         memsec::free(p);
     }
 }
@@ -82,3 +82,99 @@ fn malloc_mprotect_1_test() {
         memsec::free(x);
     }
 }
+
+#[test]
+fn malloc_onfault_test() {
+    unsafe {
+        let opts = memsec::AllocOptions::new().onfault(true);
+
+        let mut x = memsec::malloc_sized_with(1 << 20, opts).unwrap();
+        memsec::memset(x.as_mut().as_mut_ptr().add(4096 * 3), 0x04, 4096);
+        assert!(memsec::memeq(
+            x.as_ref().as_ptr().add(4096 * 3),
+            [4; 4096].as_ptr(),
+            4096
+        ));
+        memsec::free(x);
+
+        let mut p: NonNull<u64> = memsec::malloc_with(opts).unwrap();
+        *p.as_mut() = u64::MAX;
+        assert_eq!(*p.as_ref(), u64::MAX);
+        memsec::free(p);
+    }
+}
+
+#[test]
+fn secret_malloc_test() {
+    unsafe {
+        let (mut x, _backend) = memsec::secret_malloc_sized(4100).unwrap();
+        memsec::memset(x.as_mut().as_mut_ptr().offset(100), 0x03, 3000);
+        assert!(memsec::mprotect(x, memsec::Prot::ReadOnly));
+        assert!(memsec::mprotect(x, memsec::Prot::ReadWrite));
+        memsec::secret_free(x);
+    }
+}
+
+#[test]
+fn malloc_label_test() {
+    unsafe {
+        let opts = memsec::AllocOptions::new().label("tls-session-key");
+        let p: NonNull<[u8; 16]> = memsec::malloc_with(opts).unwrap();
+        assert_eq!(memsec::label(p), Some("tls-session-key"));
+
+        #[cfg(target_os = "linux")]
+        {
+            // the kernel may be built without `CONFIG_ANON_VMA_NAME`
+            let probe = libc::mmap(
+                std::ptr::null_mut(),
+                4096,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+            assert_ne!(probe, libc::MAP_FAILED);
+            let supported = libc::prctl(0x5356_4d41, 0, probe, 4096, b"probe\0".as_ptr()) == 0;
+            libc::munmap(probe, 4096);
+
+            let maps = std::fs::read_to_string("/proc/self/maps").unwrap();
+            assert_eq!(maps.contains("[anon:memsec:tls-session-key]"), supported);
+        }
+
+        memsec::free(p);
+
+        let q: NonNull<u64> = memsec::malloc().unwrap();
+        assert_eq!(memsec::label(q), None);
+        memsec::free(q);
+    }
+}
+
+#[test]
+fn init_test() {
+    assert_eq!(memsec::init(), Ok(()));
+    assert_eq!(memsec::init(), Ok(()));
+
+    let page_size = memsec::page_size();
+    assert!(page_size.is_power_of_two());
+    #[cfg(unix)]
+    assert_eq!(page_size, unsafe { libc::sysconf(libc::_SC_PAGESIZE) }
+        as usize);
+}
+
+#[test]
+fn malloc_canary_test() {
+    unsafe fn canary(p: NonNull<[u8]>) -> [u8; 16] {
+        *(p.as_ptr() as *const u8).sub(16).cast::<[u8; 16]>()
+    }
+
+    unsafe {
+        let p: NonNull<[u8]> = memsec::malloc_sized(32).unwrap();
+        let q: NonNull<[u8]> = memsec::malloc_sized(32).unwrap();
+
+        // each allocation gets its own canary
+        assert_ne!(canary(p), canary(q));
+
+        memsec::free(p);
+        memsec::free(q);
+    }
+}
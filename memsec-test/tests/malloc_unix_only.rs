@@ -6,6 +6,7 @@ procspawn::enable_test_support!();
 use std::time::Duration;
 use serde::{Deserialize, Serialize};
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 enum Offset {
     AddOffset(usize),
@@ -0,0 +1,112 @@
+#![cfg(feature = "use_os")]
+
+use std::sync::Mutex;
+
+procspawn::enable_test_support!();
+
+// `locked_bytes` is process wide, keep the tests from racing each other.
+static LOCK: Mutex<()> = Mutex::new(());
+
+#[test]
+fn locked_bytes_test() {
+    let _guard = LOCK.lock().unwrap();
+
+    unsafe {
+        let mut x = [1u8; 4096];
+        let before = memsec::locked_bytes();
+
+        assert!(memsec::mlock(x.as_mut_ptr(), x.len()));
+        assert_eq!(memsec::locked_bytes(), before + x.len());
+        assert!(memsec::munlock(x.as_mut_ptr(), x.len()));
+        assert_eq!(memsec::locked_bytes(), before);
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn mlock_onfault_test() {
+    let _guard = LOCK.lock().unwrap();
+
+    unsafe {
+        let mut x = vec![1u8; 1 << 16];
+        let before = memsec::locked_bytes();
+
+        assert!(memsec::mlock_onfault(x.as_mut_ptr(), x.len()));
+        assert_eq!(memsec::locked_bytes(), before + x.len());
+        assert!(memsec::munlock(x.as_mut_ptr(), x.len()));
+        assert_eq!(memsec::locked_bytes(), before);
+        assert!(x.iter().all(|&b| b == 0));
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn memlock_limit_test() {
+    let (soft, hard) = memsec::memlock_limit().unwrap();
+    assert!(soft <= hard);
+
+    assert!(memsec::raise_memlock_limit());
+    let (soft, hard2) = memsec::memlock_limit().unwrap();
+    assert_eq!(soft, hard);
+    assert_eq!(hard, hard2);
+}
+
+// XNU's `mlockall` is a stub that fails with `ENOSYS`
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn mlockall_munlockall_test() {
+    // `mlockall` affects the whole process, run it in a child.
+    let handle = procspawn::spawn((), |()| unsafe {
+        let flags = memsec::MlockAllFlags::FUTURE | memsec::MlockAllFlags::ONFAULT;
+        let locked = memsec::mlockall(flags);
+        let unlocked = memsec::munlockall();
+        (locked, unlocked)
+    });
+
+    assert_eq!(handle.join().unwrap(), (true, true));
+}
+
+#[test]
+fn munlock_preserve_test() {
+    let _guard = LOCK.lock().unwrap();
+
+    unsafe {
+        let mut x = [1u8; 16];
+        let before = memsec::locked_bytes();
+
+        assert!(memsec::mlock(x.as_mut_ptr(), x.len()));
+        assert!(memsec::munlock_preserve(x.as_mut_ptr(), x.len()));
+        assert_eq!(x, [1; 16]);
+        assert_eq!(memsec::locked_bytes(), before);
+    }
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn locked_region_test() {
+    #[repr(align(4096))]
+    struct Page([u8; 4096]);
+
+    let _guard = LOCK.lock().unwrap();
+
+    let mut page = Box::new(Page([1; 4096]));
+    let (x, y) = page.0.split_at_mut(2048);
+    let before = memsec::locked_bytes();
+
+    let x = memsec::LockedRegion::new(x).unwrap();
+    let locked = memsec::locked_bytes();
+    assert!(locked > before);
+
+    // `y` shares the page with `x`, it is already locked
+    let mut y = memsec::LockedRegion::new(y).unwrap();
+    assert_eq!(memsec::locked_bytes(), locked);
+
+    drop(x);
+    assert_eq!(memsec::locked_bytes(), locked);
+    y[0] = 2;
+    assert_eq!(y[0], 2);
+
+    drop(y);
+    assert_eq!(memsec::locked_bytes(), before);
+    assert!(page.0.iter().all(|&b| b == 0));
+}
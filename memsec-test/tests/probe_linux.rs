@@ -0,0 +1,26 @@
+#![cfg(feature = "alloc")]
+#![cfg(target_os = "linux")]
+
+use memsec::probe;
+
+#[test]
+fn probe_test() {
+    let (soft, hard) = probe::core_limit().unwrap();
+    assert!(soft <= hard);
+
+    assert!(probe::core_pattern().is_some());
+    assert!(probe::swap_devices().is_some());
+
+    #[cfg(feature = "alloc_ext")]
+    assert_eq!(
+        probe::memfd_secret_supported(),
+        memsec::memfd_secret_supported()
+    );
+
+    if let Some(thp) = probe::transparent_hugepages() {
+        assert!(["always", "madvise", "never"].contains(&thp.as_str()));
+    }
+    if let Some(scope) = probe::ptrace_scope() {
+        assert!(scope <= 3);
+    }
+}
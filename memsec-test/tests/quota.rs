@@ -0,0 +1,94 @@
+#![cfg(feature = "alloc")]
+
+use memsec::{AllocOptions, Error, Quota};
+use std::ptr::NonNull;
+
+procspawn::enable_test_support!();
+
+#[test]
+fn quota_allocations_test() {
+    // the quota is process-wide, keep other tests out
+    let handle = procspawn::spawn((), |()| unsafe {
+        assert_eq!(memsec::quota(), Quota::default());
+        let quota = Quota {
+            max_bytes: None,
+            max_allocations: Some(2),
+        };
+        memsec::set_quota(quota);
+        assert_eq!(memsec::quota(), quota);
+
+        let p: NonNull<u64> = memsec::malloc().unwrap();
+        let q: NonNull<u64> = memsec::malloc().unwrap();
+        assert_eq!(
+            memsec::try_malloc_with::<u64>(AllocOptions::new()),
+            Err(Error::QuotaExceeded)
+        );
+        assert!(memsec::malloc_sized(8).is_none());
+
+        // freed allocations give their share back
+        memsec::free(p);
+        let p: NonNull<u64> = memsec::malloc().unwrap();
+
+        memsec::set_quota(Quota::default());
+        let r: NonNull<u64> = memsec::malloc().unwrap();
+
+        memsec::free(p);
+        memsec::free(q);
+        memsec::free(r);
+    });
+
+    handle.join().unwrap();
+}
+
+#[test]
+fn quota_bytes_test() {
+    let handle = procspawn::spawn((), |()| unsafe {
+        let page_size = memsec::page_size();
+        memsec::set_quota(Quota {
+            max_bytes: Some(page_size * 5),
+            max_allocations: None,
+        });
+
+        // one data page, plus the header and guard pages
+        let p: NonNull<[u8]> = memsec::malloc_sized(8).unwrap();
+        assert_eq!(
+            memsec::try_malloc_sized_with(page_size, AllocOptions::new()),
+            Err(Error::QuotaExceeded)
+        );
+        memsec::free(p);
+
+        let p = memsec::try_malloc_sized_with(page_size, AllocOptions::new()).unwrap();
+        memsec::free(p);
+    });
+
+    handle.join().unwrap();
+}
+
+#[cfg(all(feature = "alloc_ext", target_os = "linux"))]
+#[test]
+fn quota_memfd_secret_test() {
+    let handle = procspawn::spawn((), |()| unsafe {
+        if !memsec::memfd_secret_supported() {
+            return;
+        }
+
+        memsec::set_quota(Quota {
+            max_bytes: None,
+            max_allocations: Some(1),
+        });
+
+        // `malloc` and `memfd_secret` share the quota
+        let p: NonNull<u64> = memsec::malloc().unwrap();
+        assert_eq!(
+            memsec::try_memfd_secret_with::<u64>(AllocOptions::new()),
+            Err(Error::QuotaExceeded)
+        );
+        memsec::free(p);
+
+        let p: NonNull<u64> = memsec::memfd_secret().unwrap();
+        assert!(memsec::malloc::<u64>().is_none());
+        memsec::free_memfd_secret(p);
+    });
+
+    handle.join().unwrap();
+}
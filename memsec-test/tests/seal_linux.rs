@@ -0,0 +1,92 @@
+#![cfg(feature = "alloc")]
+#![cfg(target_os = "linux")]
+
+use std::ptr::NonNull;
+
+procspawn::enable_test_support!();
+
+#[test]
+fn seal_guard_pages_test() {
+    if !memsec::seal_supported() {
+        return;
+    }
+
+    unsafe {
+        let page_size = libc::sysconf(libc::_SC_PAGESIZE) as usize;
+        let opts = memsec::AllocOptions::new().seal(true);
+        let mut p: NonNull<[u8; 16]> = memsec::malloc_with(opts).unwrap();
+        *p.as_mut() = [0x42; 16];
+
+        // the guard page before the data can't be made accessible anymore
+        let data = (p.as_ptr() as usize) & !(page_size - 1);
+        let guard = (data - page_size) as *mut libc::c_void;
+        assert_ne!(libc::mprotect(guard, page_size, libc::PROT_READ), 0);
+
+        // the data pages still can
+        assert!(memsec::mprotect(p, memsec::Prot::ReadOnly));
+        assert!(memsec::mprotect(p, memsec::Prot::ReadWrite));
+        memsec::free(p);
+    }
+}
+
+#[test]
+fn seal_readonly_test() {
+    unsafe {
+        // only sealed allocations can be sealed read-only
+        let p: NonNull<[u8; 16]> = memsec::malloc().unwrap();
+        assert!(!memsec::seal_readonly(p));
+        memsec::free(p);
+
+        let opts = memsec::AllocOptions::new().seal(true);
+        let mut p: NonNull<[u8; 16]> = memsec::malloc_with(opts).unwrap();
+        *p.as_mut() = [0x42; 16];
+
+        assert_eq!(memsec::seal_readonly(p), memsec::seal_supported());
+        if memsec::seal_supported() {
+            assert!(!memsec::mprotect(p, memsec::Prot::ReadWrite));
+            assert_eq!(*p.as_ref(), [0x42; 16]);
+        }
+
+        // a no-op for sealed read-only data
+        memsec::free(p);
+    }
+}
+
+#[test]
+fn seal_readonly_accounting_test() {
+    if !memsec::seal_supported() {
+        return;
+    }
+
+    // quota and counters are process-wide, keep other tests out
+    let handle = procspawn::spawn((), |()| unsafe {
+        memsec::set_quota(memsec::Quota {
+            max_bytes: None,
+            max_allocations: Some(1),
+        });
+
+        let opts = memsec::AllocOptions::new().seal(true);
+        let p: NonNull<[u8; 16]> = memsec::malloc_with(opts).unwrap();
+        assert!(memsec::seal_readonly(p));
+        memsec::free(p);
+
+        // the data stays mapped, so it still counts
+        assert_eq!(memsec::stats().malloc.live_allocations, 1);
+        let opts = memsec::AllocOptions::new();
+        memsec::try_malloc_with::<[u8; 16]>(opts) == Err(memsec::Error::QuotaExceeded)
+    });
+
+    assert!(handle.join().unwrap());
+}
+
+#[cfg(feature = "alloc_ext")]
+#[test]
+fn seal_memfd_secret_test() {
+    unsafe {
+        let opts = memsec::AllocOptions::new().seal(true);
+        let mut p: NonNull<u64> = memsec::try_memfd_secret_with(opts).unwrap();
+        *p.as_mut() = u64::MAX;
+        assert_eq!(*p.as_ref(), u64::MAX);
+        memsec::free_memfd_secret(p);
+    }
+}
@@ -0,0 +1,123 @@
+#![cfg(feature = "alloc")]
+
+use std::ptr::NonNull;
+
+procspawn::enable_test_support!();
+
+#[test]
+fn stats_malloc_test() {
+    // counters are process-wide, keep other tests out
+    let handle = procspawn::spawn((), |()| unsafe {
+        let page_size = memsec::page_size();
+        assert_eq!(memsec::stats(), memsec::Stats::default());
+
+        let p: NonNull<[u8]> = memsec::malloc_sized(100).unwrap();
+        let stats = memsec::stats().malloc;
+        assert_eq!(stats.live_allocations, 1);
+        assert_eq!(stats.requested_bytes, 100);
+        assert_eq!(stats.mapped_bytes, page_size * 4);
+        assert!(stats.mprotect_calls >= 3);
+        assert_eq!(
+            stats.locked_bytes + stats.mlock_failures as usize * page_size,
+            page_size
+        );
+
+        assert!(memsec::mprotect(p, memsec::Prot::ReadOnly));
+        assert_eq!(
+            memsec::stats().malloc.mprotect_calls,
+            stats.mprotect_calls + 1
+        );
+
+        memsec::free(p);
+        let stats = memsec::stats().malloc;
+        assert_eq!(stats.live_allocations, 0);
+        assert_eq!(stats.requested_bytes, 0);
+        assert_eq!(stats.mapped_bytes, 0);
+        assert_eq!(stats.locked_bytes, 0);
+        assert_eq!(
+            memsec::stats().memfd_secret,
+            memsec::BackendStats::default()
+        );
+    });
+
+    handle.join().unwrap();
+}
+
+#[test]
+fn stats_canary_test() {
+    fn handler(_: &memsec::CorruptionReport<'_>) -> ! {
+        panic!("{}", memsec::stats().malloc.canary_failures);
+    }
+
+    let handle = procspawn::spawn((), |()| unsafe {
+        memsec::set_corruption_handler(handler);
+
+        let p: NonNull<[u8]> = memsec::malloc_sized(32).unwrap();
+        *(p.as_ptr() as *mut u8).sub(1) ^= 1;
+        memsec::free(p);
+    });
+
+    let err = handle.join().unwrap_err();
+    assert_eq!(err.panic_info().unwrap().message(), "1");
+}
+
+#[cfg(all(feature = "alloc_ext", target_os = "linux"))]
+#[test]
+fn stats_memfd_secret_test() {
+    let handle = procspawn::spawn((), |()| unsafe {
+        if !memsec::memfd_secret_supported() {
+            return;
+        }
+
+        let page_size = memsec::page_size();
+        let p: NonNull<[u8]> = memsec::memfd_secret_sized(100).unwrap();
+        let stats = memsec::stats().memfd_secret;
+        assert_eq!(stats.live_allocations, 1);
+        assert_eq!(stats.requested_bytes, 100);
+        assert_eq!(stats.mapped_bytes, page_size * 4);
+        assert_eq!(stats.locked_bytes, 0);
+        assert_eq!(memsec::stats().malloc.live_allocations, 0);
+
+        memsec::free_memfd_secret(p);
+        let stats = memsec::stats().memfd_secret;
+        assert_eq!(stats.live_allocations, 0);
+        assert_eq!(stats.mapped_bytes, 0);
+    });
+
+    handle.join().unwrap();
+}
+
+#[test]
+fn stats_label_test() {
+    let handle = procspawn::spawn((), |()| unsafe {
+        let page_size = memsec::page_size();
+        let opts = memsec::AllocOptions::new().label("stats-key");
+
+        let p: NonNull<[u8]> = memsec::malloc_sized_with(100, opts).unwrap();
+        let q: NonNull<[u8]> = memsec::malloc_sized(100).unwrap();
+        let labels = memsec::label_stats();
+        assert_eq!(labels.len(), 1);
+        let stats = labels["stats-key"];
+        assert_eq!(stats.live_allocations, 1);
+        assert_eq!(stats.requested_bytes, 100);
+        assert_eq!(stats.mapped_bytes, page_size * 4);
+
+        #[cfg(all(feature = "alloc_ext", target_os = "linux"))]
+        if memsec::memfd_secret_supported() {
+            let arena = memsec::SecretArena::with_capacity(page_size).unwrap();
+            let r: NonNull<[u8]> = arena.malloc_sized_with(50, opts).unwrap();
+            assert_eq!(memsec::label(r), Some("stats-key"));
+            let stats = memsec::label_stats()["stats-key"];
+            assert_eq!(stats.live_allocations, 2);
+            assert_eq!(stats.requested_bytes, 150);
+            arena.free(r);
+        }
+
+        memsec::free(p);
+        memsec::free(q);
+        let stats = memsec::label_stats()["stats-key"];
+        assert_eq!(stats, memsec::LabelStats::default());
+    });
+
+    handle.join().unwrap();
+}
@@ -0,0 +1,277 @@
+//! SecretArena
+//! Many guarded allocations sharing one `memfd_secret` file descriptor.
+
+extern crate std;
+use self::std::collections::BTreeSet;
+use self::std::os::unix::io::{AsFd, BorrowedFd};
+use self::std::sync::{Mutex, MutexGuard};
+use self::std::vec::Vec;
+use super::linux::{map_data_pages, open_memfd_secret, release_memfd_secret};
+use crate::{alloc::*, Error, Prot};
+use core::mem;
+use core::ptr::{self, NonNull};
+
+/// Default `SecretArena` capacity.
+const DEFAULT_CAPACITY: usize = 256 << 20;
+
+/// An arena of `memfd_secret` backed allocations.
+///
+/// All allocations share one `memfd_secret` file.
+/// Only the data pages live in the file, every allocation still gets
+/// its own header page, guard pages and canary.
+///
+/// The kernel only allows to size a `memfd_secret` file once,
+/// so the file is created with the full capacity of the arena.
+/// Its pages are only allocated when they are touched.
+///
+/// Allocations must be freed with `SecretArena::free`.
+/// On drop, allocations still alive are wiped and unmapped, then the file is closed.
+pub struct SecretArena {
+    fd: libc::c_int,
+    state: Mutex<ArenaState>,
+}
+
+struct ArenaState {
+    /// Size of the file.
+    capacity: usize,
+    /// End of the part of the file used so far.
+    top: usize,
+    /// Free `(offset, len)` ranges of the file, sorted by offset.
+    free: Vec<(usize, usize)>,
+    /// Base pointers of the allocations alive.
+    live: BTreeSet<usize>,
+}
+
+impl ArenaState {
+    /// Take `size` bytes of the file, first fit.
+    fn take(&mut self, size: usize) -> Option<usize> {
+        if let Some(i) = self.free.iter().position(|&(_, len)| len >= size) {
+            let (offset, len) = self.free[i];
+            if len == size {
+                self.free.remove(i);
+            } else {
+                self.free[i] = (offset + size, len - size);
+            }
+            return Some(offset);
+        }
+
+        let offset = self.top;
+        let top = offset
+            .checked_add(size)
+            .filter(|&top| top <= self.capacity)?;
+        self.top = top;
+        Some(offset)
+    }
+
+    /// Give back a range of the file, merging it with its neighbours.
+    fn give(&mut self, offset: usize, size: usize) {
+        let i = self.free.partition_point(|&(o, _)| o < offset);
+        self.free.insert(i, (offset, size));
+
+        if i + 1 < self.free.len() && offset + size == self.free[i + 1].0 {
+            self.free[i].1 += self.free[i + 1].1;
+            self.free.remove(i + 1);
+        }
+        if i > 0 && self.free[i - 1].0 + self.free[i - 1].1 == offset {
+            self.free[i - 1].1 += self.free[i].1;
+            self.free.remove(i);
+        }
+    }
+}
+
+impl SecretArena {
+    /// Create an empty arena of 256 MiB.
+    ///
+    /// Fails if `memfd_secret` is not available.
+    pub fn new() -> Result<SecretArena, Error> {
+        SecretArena::with_capacity(DEFAULT_CAPACITY)
+    }
+
+    /// Create an empty arena that can hold `capacity` bytes of data pages.
+    ///
+    /// Fails if `memfd_secret` is not available.
+    pub fn with_capacity(capacity: usize) -> Result<SecretArena, Error> {
+        let page_mask = page_size() - 1;
+        if capacity >= usize::MAX - page_mask {
+            return Err(Error::TooLarge);
+        }
+
+        let capacity = (capacity + page_mask) & !page_mask;
+        let fd = open_memfd_secret(libc::O_CLOEXEC)?;
+
+        // File size is set using ftruncate
+        if unsafe { libc::ftruncate(fd, capacity as libc::off_t) } != 0 {
+            let err = Error::last_os_error();
+            unsafe { libc::close(fd) };
+            return Err(err);
+        }
+
+        Ok(SecretArena {
+            fd,
+            state: Mutex::new(ArenaState {
+                capacity,
+                top: 0,
+                free: Vec::new(),
+                live: BTreeSet::new(),
+            }),
+        })
+    }
+
+    unsafe fn _malloc(&self, size: usize, opts: &AllocOptions) -> Option<*mut u8> {
+        init().ok()?;
+        let page_size = rt().page_size;
+
+        if size >= usize::MAX - page_size * 4 {
+            return None;
+        }
+
+        let size_with_canary = CANARY_SIZE + size;
+        let unprotected_size = page_round(size_with_canary);
+
+        let offset = self.take(unprotected_size)?;
+        let base_ptr = match map_data_pages(self.fd, offset, unprotected_size) {
+            Ok(base_ptr) => base_ptr,
+            Err(_) => {
+                self.give(offset, unprotected_size);
+                return None;
+            }
+        };
+        let unprotected_ptr = base_ptr.add(page_size * 2);
+
+        let canary_ptr = unprotected_ptr.add(unprotected_size - size_with_canary);
+        let user_ptr = canary_ptr.add(CANARY_SIZE);
+        let counter = canary::write(canary_ptr);
+
+        stats::MEMFD_SECRET.mprotect(base_ptr, page_size, Prot::ReadWrite);
+        let header = Header {
+            unprotected_size,
+            size,
+            fd: self.fd,
+            flags: FLAG_MEMFD | FLAG_ARENA | opts.flags(),
+            offset,
+            counter,
+            canary: [0; CANARY_SIZE],
+            label: opts.label,
+            slot: 0,
+        };
+        ptr::write(base_ptr as *mut Header, header);
+        atfork::register(base_ptr);
+        stats::MEMFD_SECRET.mprotect(base_ptr, page_size, Prot::ReadOnly);
+        if let Some(label) = opts.label {
+            label::name_pages(base_ptr, unprotected_size, label);
+        }
+
+        assert_eq!(unprotected_ptr_from_user_ptr(user_ptr), unprotected_ptr);
+
+        stats::MEMFD_SECRET.allocated(&header);
+        self.lock().live.insert(base_ptr as usize);
+
+        Some(user_ptr)
+    }
+
+    #[inline]
+    fn lock(&self) -> MutexGuard<'_, ArenaState> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn take(&self, size: usize) -> Option<usize> {
+        self.lock().take(size)
+    }
+
+    fn give(&self, offset: usize, size: usize) {
+        self.lock().give(offset, size);
+    }
+
+    /// `memfd_secret` backed allocation from the arena.
+    #[inline]
+    pub unsafe fn malloc<T>(&self) -> Option<NonNull<T>> {
+        self.malloc_with(AllocOptions::new())
+    }
+
+    /// `memfd_secret` backed `sized` allocation from the arena.
+    #[inline]
+    pub unsafe fn malloc_sized(&self, size: usize) -> Option<NonNull<[u8]>> {
+        self.malloc_sized_with(size, AllocOptions::new())
+    }
+
+    /// `memfd_secret` backed allocation from the arena with options.
+    ///
+    /// Only `label` and `inheritable` apply, the file belongs to the arena.
+    #[inline]
+    pub unsafe fn malloc_with<T>(&self, opts: AllocOptions) -> Option<NonNull<T>> {
+        self._malloc(mem::size_of::<T>(), &opts).map(|memptr| {
+            ptr::write_bytes(memptr, GARBAGE_VALUE, mem::size_of::<T>());
+            NonNull::new_unchecked(memptr as *mut T)
+        })
+    }
+
+    /// `memfd_secret` backed `sized` allocation from the arena with options.
+    ///
+    /// Only `label` and `inheritable` apply, the file belongs to the arena.
+    #[inline]
+    pub unsafe fn malloc_sized_with(
+        &self,
+        size: usize,
+        opts: AllocOptions,
+    ) -> Option<NonNull<[u8]>> {
+        self._malloc(size, &opts).map(|memptr| {
+            ptr::write_bytes(memptr, GARBAGE_VALUE, size);
+            NonNull::new_unchecked(ptr::slice_from_raw_parts_mut(memptr, size))
+        })
+    }
+
+    /// Secure `free` for arena allocations.
+    ///
+    /// The data pages are wiped and their part of the file is reused
+    /// by later allocations.
+    pub unsafe fn free<T: ?Sized>(&self, memptr: NonNull<T>) {
+        let memptr = memptr.as_ptr() as *mut u8;
+
+        // get unprotected ptr
+        let canary_ptr = memptr.sub(CANARY_SIZE);
+        let unprotected_ptr = unprotected_ptr_from_user_ptr(memptr);
+        let base_ptr = unprotected_ptr.sub(rt().page_size * 2);
+        let header = read_header(base_ptr);
+
+        // check
+        check_canary(canary_ptr, &header);
+
+        if header.flags & FLAG_ARENA == 0 || header.fd != self.fd {
+            corrupted(memptr, header.size, None, CorruptionKind::Header);
+        }
+
+        atfork::unregister(&header);
+
+        release_memfd_secret(base_ptr, header, true);
+        let mut state = self.lock();
+        state.live.remove(&(base_ptr as usize));
+        state.give(header.offset, header.unprotected_size);
+    }
+}
+
+impl AsFd for SecretArena {
+    #[inline]
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        unsafe { BorrowedFd::borrow_raw(self.fd) }
+    }
+}
+
+impl Drop for SecretArena {
+    fn drop(&mut self) {
+        let state = self.state.get_mut().unwrap_or_else(|err| err.into_inner());
+
+        // the allocations keep the file descriptor in their header, release them first
+        for base_ptr in mem::take(&mut state.live) {
+            unsafe {
+                let base_ptr = base_ptr as *mut u8;
+                let header = read_header(base_ptr);
+                atfork::unregister(&header);
+                release_memfd_secret(base_ptr, header, true);
+            }
+        }
+
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
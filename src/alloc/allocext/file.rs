@@ -0,0 +1,157 @@
+//! SecretFile
+//! A sealed `memfd_create` file holding a secret, for tools that want a path.
+
+extern crate std;
+use self::std::format;
+use self::std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd};
+use self::std::path::PathBuf;
+use crate::{Error, Prot};
+use core::ptr;
+
+const SEALS: libc::c_int =
+    libc::F_SEAL_FUTURE_WRITE | libc::F_SEAL_GROW | libc::F_SEAL_SHRINK | libc::F_SEAL_SEAL;
+
+/// A secret in a sealed `memfd_create` file.
+///
+/// The file can't be written, grown or shrunk once created,
+/// readers get it through `path` or an inherited file descriptor.
+/// It uses `F_SEAL_FUTURE_WRITE` rather than `F_SEAL_WRITE`,
+/// which would forbid the `mlock`ed mapping the file is wiped through on drop.
+///
+/// Unlike `memfd_secret`, the pages stay in the kernel direct map.
+pub struct SecretFile {
+    fd: OwnedFd,
+    ptr: *mut u8,
+    len: usize,
+    locked: bool,
+}
+
+unsafe impl Send for SecretFile {}
+unsafe impl Sync for SecretFile {}
+
+impl SecretFile {
+    /// Write `secret` into a new sealed file.
+    ///
+    /// The file descriptor is `O_CLOEXEC` until `set_inheritable`.
+    pub fn new(secret: &[u8]) -> Result<SecretFile, Error> {
+        let len = secret.len();
+
+        let fd = unsafe {
+            libc::memfd_create(
+                b"memsec\0".as_ptr() as *const libc::c_char,
+                libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING,
+            )
+        };
+        if fd < 0 {
+            return Err(Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        if len as u64 > libc::off_t::MAX as u64 {
+            return Err(Error::TooLarge);
+        }
+        if unsafe { libc::ftruncate(fd.as_raw_fd(), len as libc::off_t) } != 0 {
+            return Err(Error::last_os_error());
+        }
+
+        let mut file = SecretFile {
+            fd,
+            ptr: ptr::null_mut(),
+            len,
+            locked: false,
+        };
+
+        if len != 0 {
+            let ptr = unsafe {
+                libc::mmap(
+                    ptr::null_mut(),
+                    len,
+                    Prot::ReadWrite,
+                    libc::MAP_SHARED,
+                    file.fd.as_raw_fd(),
+                    0,
+                )
+            };
+            if ptr == libc::MAP_FAILED {
+                return Err(Error::last_os_error());
+            }
+            file.ptr = ptr as *mut u8;
+
+            unsafe {
+                file.locked = crate::mlock(file.ptr, len);
+                ptr::copy_nonoverlapping(secret.as_ptr(), file.ptr, len);
+            }
+        }
+
+        if unsafe { libc::fcntl(file.fd.as_raw_fd(), libc::F_ADD_SEALS, SEALS) } != 0 {
+            return Err(Error::last_os_error());
+        }
+
+        Ok(file)
+    }
+
+    /// `/proc/self/fd/N` path of the file.
+    ///
+    /// Child processes can only open it if they inherit the file descriptor.
+    #[inline]
+    pub fn path(&self) -> PathBuf {
+        PathBuf::from(format!("/proc/self/fd/{}", self.fd.as_raw_fd()))
+    }
+
+    /// Let child processes inherit the file descriptor, i.e. clear `FD_CLOEXEC`.
+    pub fn set_inheritable(&self, inheritable: bool) -> Result<(), Error> {
+        let fd = self.fd.as_raw_fd();
+
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+        if flags < 0 {
+            return Err(Error::last_os_error());
+        }
+
+        let flags = if inheritable {
+            flags & !libc::FD_CLOEXEC
+        } else {
+            flags | libc::FD_CLOEXEC
+        };
+        if unsafe { libc::fcntl(fd, libc::F_SETFD, flags) } != 0 {
+            return Err(Error::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    /// Size of the secret.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the secret is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl AsFd for SecretFile {
+    #[inline]
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl Drop for SecretFile {
+    fn drop(&mut self) {
+        if self.ptr.is_null() {
+            return;
+        }
+
+        unsafe {
+            if self.locked {
+                crate::munlock(self.ptr, self.len);
+            } else {
+                crate::memzero(self.ptr, self.len);
+            }
+            libc::munmap(self.ptr as *mut libc::c_void, self.len);
+        }
+    }
+}
@@ -1,136 +1,296 @@
 extern crate std;
-use self::std::process::abort;
-use crate::{alloc::*, Prot};
+use self::std::os::unix::io::BorrowedFd;
+use crate::alloc::once::OnceCell;
+use crate::{alloc::*, Error, Prot};
 use core::mem::{self, size_of};
 use core::ptr::{self, NonNull};
-use core::slice;
 
-use self::memfd_secret_alloc::*;
+pub(crate) use self::memfd_secret_alloc::*;
 
 mod memfd_secret_alloc {
     use super::*;
     use core::convert::TryInto;
 
     #[inline]
-    pub unsafe fn alloc_memfd_secret(size: usize) -> Option<(NonNull<u8>, libc::c_int)> {
-        let fd: Result<libc::c_int, _> = libc::syscall(libc::SYS_memfd_secret, 0).try_into();
+    pub(crate) fn open_memfd_secret(flags: libc::c_int) -> Result<libc::c_int, Error> {
+        let fd: Result<libc::c_int, _> =
+            unsafe { libc::syscall(libc::SYS_memfd_secret, flags) }.try_into();
 
-        let fd = fd.ok().filter(|&fd| fd >= 0)?;
+        match fd {
+            Ok(fd) if fd >= 0 => Ok(fd),
+            _ => Err(Error::last_os_error()),
+        }
+    }
 
-        // File size is set using ftruncate
-        let _ = libc::ftruncate(fd, size as libc::off_t);
-
-        let ptr = libc::mmap(
-            ptr::null_mut(),
-            size,
-            Prot::ReadWrite,
-            libc::MAP_SHARED,
-            fd,
-            0,
-        );
+    /// Create a `memfd_secret` file for `unprotected_size` bytes of data pages
+    /// and map it behind anonymous header and guard pages.
+    ///
+    /// Only the data pages live in the file, so that a process receiving
+    /// the file descriptor never sees the header. Returns the base pointer.
+    #[inline]
+    pub unsafe fn alloc_memfd_secret(
+        unprotected_size: usize,
+        flags: libc::c_int,
+    ) -> Result<(*mut u8, libc::c_int), Error> {
+        let fd = open_memfd_secret(flags)?;
 
-        if ptr == libc::MAP_FAILED {
-            return None;
+        // File size is set using ftruncate
+        if libc::ftruncate(fd, unprotected_size as libc::off_t) != 0 {
+            let err = Error::last_os_error();
+            libc::close(fd);
+            return Err(err);
         }
 
-        NonNull::new(ptr as *mut u8).map(|ptr| (ptr, fd))
+        match map_data_pages(fd, 0, unprotected_size) {
+            Ok(base_ptr) => Ok((base_ptr, fd)),
+            Err(err) => {
+                libc::close(fd);
+                Err(err)
+            }
+        }
     }
 }
 
-unsafe fn _memfd_secret(size: usize) -> Option<*mut u8> {
-    ALLOC_INIT.call_once(|| alloc_init());
+static MEMFD_SECRET_SUPPORTED: OnceCell<bool> = OnceCell::new();
 
-    //Assert size of unprotected_size (usize) and fd (i32) is less than PAGE_SIZE before allocating memory
-    assert!(size_of::<usize>() + size_of::<i32>() <= PAGE_SIZE);
+/// Whether the kernel supports `memfd_secret`.
+///
+/// It may be missing (`ENOSYS`) or disabled with `secretmem.enable=0`.
+/// The result is probed once and cached.
+pub fn memfd_secret_supported() -> bool {
+    *MEMFD_SECRET_SUPPORTED.get_or_init(crate::probe::memfd_secret_supported)
+}
 
-    if size >= ::core::usize::MAX - PAGE_SIZE * 4 {
-        return None;
+pub(crate) unsafe fn _memfd_secret(size: usize, opts: &AllocOptions) -> Result<*mut u8, Error> {
+    init()?;
+    let page_size = rt().page_size;
+
+    //Assert size of header is less than PAGE_SIZE before allocating memory
+    assert!(size_of::<Header>() <= page_size);
+
+    if size >= usize::MAX - page_size * 4 {
+        return Err(Error::TooLarge);
+    }
+
+    // `MADV_WIPEONFORK` only applies to private anonymous mappings
+    if opts.fork == ForkPolicy::Wipe {
+        return Err(Error::Os(libc::EINVAL));
     }
 
     // aligned alloc ptr
     let size_with_canary = CANARY_SIZE + size;
     let unprotected_size = page_round(size_with_canary);
-    let total_size = PAGE_SIZE + PAGE_SIZE + unprotected_size + PAGE_SIZE;
-    let (base_ptr, fd) = alloc_memfd_secret(total_size)?;
-    let base_ptr = base_ptr.as_ptr();
-    let fd_ptr = base_ptr.add(size_of::<usize>());
-    let unprotected_ptr = base_ptr.add(PAGE_SIZE * 2);
-
-    // mprotect can be used to change protection flag after mmap setup
-    // https://www.gnu.org/software/libc/manual/html_node/Memory-Protection.html#index-mprotect
-    _mprotect(base_ptr.add(PAGE_SIZE), PAGE_SIZE, Prot::NoAccess);
-    _mprotect(
-        unprotected_ptr.add(unprotected_size),
-        PAGE_SIZE,
-        Prot::NoAccess,
-    );
+    let total_size = page_size + page_size + unprotected_size + page_size;
+    quota::reserve(total_size)?;
+    let (base_ptr, fd) = match alloc_memfd_secret(unprotected_size, opts.memfd_flags()) {
+        Ok(res) => res,
+        Err(err) => {
+            quota::unreserve(total_size);
+            return Err(err);
+        }
+    };
+    let unprotected_ptr = base_ptr.add(page_size * 2);
+
+    let sealed = opts.sealed();
+    let mut flags = FLAG_MEMFD | FLAG_QUOTA | opts.flags();
+    if sealed {
+        flags |= FLAG_SEALED;
+    }
+
+    match set_fork_policy(unprotected_ptr, unprotected_size, opts.fork) {
+        Ok(fork_flags) => flags |= fork_flags,
+        Err(err) => {
+            libc::munmap(base_ptr.cast(), total_size);
+            libc::close(fd);
+            quota::unreserve(total_size);
+            return Err(err);
+        }
+    }
 
     let canary_ptr = unprotected_ptr.add(unprotected_size - size_with_canary);
     let user_ptr = canary_ptr.add(CANARY_SIZE);
-    #[allow(static_mut_refs)]
-    ptr::copy_nonoverlapping(CANARY.as_ptr(), canary_ptr, CANARY_SIZE);
-    ptr::write_unaligned(base_ptr as *mut usize, unprotected_size);
-    ptr::write_unaligned(fd_ptr as *mut libc::c_int, fd);
-    _mprotect(base_ptr, PAGE_SIZE, Prot::ReadOnly);
+    let counter = canary::write(canary_ptr);
+    let mut header = Header {
+        unprotected_size,
+        size,
+        fd,
+        flags,
+        offset: 0,
+        counter,
+        canary: [0; CANARY_SIZE],
+        label: opts.label,
+        slot: 0,
+    };
+    stats::MEMFD_SECRET.mprotect(base_ptr, page_size, Prot::ReadWrite);
+    ptr::write(base_ptr as *mut Header, header);
+    atfork::register(base_ptr);
+    stats::MEMFD_SECRET.mprotect(base_ptr, page_size, Prot::ReadOnly);
+    if let Some(label) = opts.label {
+        label::name_pages(base_ptr, unprotected_size, label);
+    }
+    if sealed {
+        header.flags = seal::seal_guard_pages(base_ptr, unprotected_size);
+    }
 
     assert_eq!(unprotected_ptr_from_user_ptr(user_ptr), unprotected_ptr);
 
-    Some(user_ptr)
+    stats::MEMFD_SECRET.allocated(&header);
+
+    Ok(user_ptr)
+}
+
+/// Reserve a guarded range whose data pages are mapped from `fd` at `offset`.
+///
+/// The header and guard pages are anonymous `Prot::NoAccess` memory,
+/// returns the base pointer.
+pub(crate) unsafe fn map_data_pages(
+    fd: libc::c_int,
+    offset: usize,
+    unprotected_size: usize,
+) -> Result<*mut u8, Error> {
+    let page_size = rt().page_size;
+
+    let total_size = page_size + page_size + unprotected_size + page_size;
+
+    // reserve the whole range, header and guard pages are anonymous memory
+    let base_ptr = libc::mmap(
+        ptr::null_mut(),
+        total_size,
+        Prot::NoAccess,
+        libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+        -1,
+        0,
+    );
+    if base_ptr == libc::MAP_FAILED {
+        return Err(Error::last_os_error());
+    }
+    let base_ptr = base_ptr as *mut u8;
+
+    // map the data pages from the file
+    let data_ptr = libc::mmap(
+        base_ptr.add(page_size * 2) as *mut libc::c_void,
+        unprotected_size,
+        Prot::ReadWrite,
+        libc::MAP_SHARED | libc::MAP_FIXED,
+        fd,
+        offset as libc::off_t,
+    );
+    if data_ptr == libc::MAP_FAILED {
+        let err = Error::last_os_error();
+        libc::munmap(base_ptr as *mut libc::c_void, total_size);
+        return Err(err);
+    }
+
+    Ok(base_ptr)
 }
 
 /// Linux specific `memfd_secret` backed allocation
 #[inline]
 pub unsafe fn memfd_secret<T>() -> Option<NonNull<T>> {
-    _memfd_secret(mem::size_of::<T>()).map(|memptr| {
+    try_memfd_secret_with(AllocOptions::new()).ok()
+}
+
+/// Linux specific `memfd_secret` backed `sized` allocation
+#[inline]
+pub unsafe fn memfd_secret_sized(size: usize) -> Option<NonNull<[u8]>> {
+    try_memfd_secret_sized_with(size, AllocOptions::new()).ok()
+}
+
+/// Linux specific `memfd_secret` backed allocation with options,
+/// reports why the allocation failed.
+///
+/// `onfault` doesn't apply, `memfd_secret` pages are never swapped out.
+/// The pages are shared with the file, so `ForkPolicy::Wipe` fails with `EINVAL`.
+#[inline]
+pub unsafe fn try_memfd_secret_with<T>(opts: AllocOptions) -> Result<NonNull<T>, Error> {
+    _memfd_secret(mem::size_of::<T>(), &opts).map(|memptr| {
         ptr::write_bytes(memptr, GARBAGE_VALUE, mem::size_of::<T>());
         NonNull::new_unchecked(memptr as *mut T)
     })
 }
 
-/// Linux specific `memfd_secret` backed `sized` allocation
+/// Linux specific `memfd_secret` backed `sized` allocation with options,
+/// reports why the allocation failed.
+///
+/// `onfault` doesn't apply, `memfd_secret` pages are never swapped out.
+/// The pages are shared with the file, so `ForkPolicy::Wipe` fails with `EINVAL`.
 #[inline]
-pub unsafe fn memfd_secret_sized(size: usize) -> Option<NonNull<[u8]>> {
-    _memfd_secret(size).map(|memptr| {
+pub unsafe fn try_memfd_secret_sized_with(
+    size: usize,
+    opts: AllocOptions,
+) -> Result<NonNull<[u8]>, Error> {
+    _memfd_secret(size, &opts).map(|memptr| {
         ptr::write_bytes(memptr, GARBAGE_VALUE, size);
-        NonNull::new_unchecked(slice::from_raw_parts_mut(memptr, size))
+        NonNull::new_unchecked(ptr::slice_from_raw_parts_mut(memptr, size))
     })
 }
 
+/// Borrow the file descriptor backing a `memfd_secret` allocation.
+///
+/// For `SecretArena` allocations, this is the file descriptor of the arena.
+/// The file descriptor must not be used after the allocation is freed.
+pub unsafe fn memfd_secret_as_fd<'a, T: ?Sized>(memptr: NonNull<T>) -> BorrowedFd<'a> {
+    let unprotected_ptr = unprotected_ptr_from_user_ptr(memptr.as_ptr() as *const u8);
+    let base_ptr = unprotected_ptr.sub(rt().page_size * 2);
+    let header = read_header(base_ptr);
+
+    if header.flags & FLAG_MEMFD == 0 {
+        corrupted(
+            memptr.as_ptr() as *const u8,
+            header.size,
+            None,
+            CorruptionKind::Header,
+        );
+    }
+
+    BorrowedFd::borrow_raw(header.fd)
+}
+
 /// Secure `free` for memfd_secret allocations,
 /// i.e. provides read write access back to mprotect guard pages
 /// and unmaps mmaped secrets
 pub unsafe fn free_memfd_secret<T: ?Sized>(memptr: NonNull<T>) {
-    use libc::c_void;
-
     let memptr = memptr.as_ptr() as *mut u8;
 
     // get unprotected ptr
     let canary_ptr = memptr.sub(CANARY_SIZE);
     let unprotected_ptr = unprotected_ptr_from_user_ptr(memptr);
-    let base_ptr = unprotected_ptr.sub(PAGE_SIZE * 2);
-    let fd_ptr = base_ptr.add(size_of::<usize>()) as *mut libc::c_int;
-    let unprotected_size = ptr::read(base_ptr as *const usize);
-    let fd = ptr::read(fd_ptr);
+    let base_ptr = unprotected_ptr.sub(rt().page_size * 2);
+    let header = read_header(base_ptr);
 
     // check
-    #[allow(static_mut_refs)]
-    if !crate::memeq(canary_ptr as *const u8, CANARY.as_ptr(), CANARY_SIZE) {
-        abort();
-    }
+    check_canary(canary_ptr, &header);
+
+    atfork::unregister(&header);
 
-    // free
-    let total_size = PAGE_SIZE + PAGE_SIZE + unprotected_size + PAGE_SIZE;
-    _mprotect(base_ptr, total_size, Prot::ReadWrite);
+    // a received allocation belongs to the sending process, only it wipes the data
+    release_memfd_secret(base_ptr, header, header.flags & FLAG_FOREIGN == 0);
+}
 
-    crate::memzero(unprotected_ptr, unprotected_size);
+/// Unmap a `memfd_secret` allocation and close its file descriptor.
+///
+/// The mapping is shared, `wipe` must be `false` when other processes still use it.
+pub(crate) unsafe fn release_memfd_secret(base_ptr: *mut u8, header: Header, wipe: bool) {
+    let (ptr, len) = match unprotect_for_release(base_ptr, &header) {
+        Some(range) => range,
+        None => return,
+    };
 
-    let res = libc::munmap(base_ptr as *mut c_void, total_size);
-    if res < 0 {
+    if wipe {
+        let unprotected_ptr = base_ptr.add(rt().page_size * 2);
+        crate::memzero(unprotected_ptr, header.unprotected_size);
+    }
+
+    if libc::munmap(ptr as *mut libc::c_void, len) < 0 {
         abort();
     }
 
-    let res = libc::close(fd);
-    if res < 0 {
+    // the file of an arena allocation belongs to the arena
+    if header.flags & FLAG_ARENA != 0 {
+        return;
+    }
+
+    if libc::close(header.fd) < 0 {
         abort();
     }
 }
@@ -4,8 +4,20 @@
 //!
 #![cfg(feature = "alloc_ext")]
 
+#[cfg(target_os = "linux")]
+mod arena;
+#[cfg(target_os = "linux")]
+mod file;
 #[cfg(target_os = "linux")]
 mod linux;
+#[cfg(target_os = "linux")]
+mod scm;
 
+#[cfg(target_os = "linux")]
+pub use self::arena::SecretArena;
+#[cfg(target_os = "linux")]
+pub use self::file::SecretFile;
 #[cfg(target_os = "linux")]
 pub use self::linux::*;
+#[cfg(target_os = "linux")]
+pub use self::scm::{recv_secret, recv_secret_with, send_secret};
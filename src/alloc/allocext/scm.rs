@@ -0,0 +1,209 @@
+//! SCM_RIGHTS
+//! Pass `memfd_secret` allocations to another local process over a unix socket.
+
+extern crate std;
+use self::std::os::unix::io::{AsRawFd, BorrowedFd};
+use super::linux::map_data_pages;
+use crate::{alloc::*, Error, Prot};
+use core::mem::{self, size_of};
+use core::ptr::{self, NonNull};
+
+/// `f_type` of the `secretmem` file system.
+const SECRETMEM_MAGIC: libc::c_long = 0x5345_434d;
+
+/// Room for one `SCM_RIGHTS` control message, aligned for `cmsghdr`.
+type ControlBuf = [u64; 4];
+
+/// Send a `memfd_secret` allocation to the process at the other end of `socket`.
+///
+/// The file descriptor goes along with the size of the allocation,
+/// the data itself never goes through the socket.
+/// Both processes share the data pages: the sending process still owns them,
+/// freeing the allocation on its side wipes them for both.
+///
+/// `SecretArena` allocations can't be sent, that would share the whole arena.
+pub unsafe fn send_secret<T: ?Sized>(
+    socket: BorrowedFd<'_>,
+    memptr: NonNull<T>,
+) -> Result<(), Error> {
+    let memptr = memptr.as_ptr() as *mut u8;
+
+    let unprotected_ptr = unprotected_ptr_from_user_ptr(memptr);
+    let base_ptr = unprotected_ptr.sub(rt().page_size * 2);
+    let header = read_header(base_ptr);
+
+    if header.flags & FLAG_MEMFD == 0 || header.flags & FLAG_ARENA != 0 {
+        return Err(Error::Os(libc::EINVAL));
+    }
+
+    let size = unprotected_ptr.add(header.unprotected_size) as usize - memptr as usize;
+    let payload = (size as u64).to_ne_bytes();
+    let mut iov = libc::iovec {
+        iov_base: payload.as_ptr() as *mut libc::c_void,
+        iov_len: payload.len(),
+    };
+
+    let mut control: ControlBuf = [0; 4];
+    let mut msg: libc::msghdr = mem::zeroed();
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = libc::CMSG_SPACE(size_of::<libc::c_int>() as u32) as _;
+
+    let cmsg = libc::CMSG_FIRSTHDR(&msg);
+    (*cmsg).cmsg_level = libc::SOL_SOCKET;
+    (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+    (*cmsg).cmsg_len = libc::CMSG_LEN(size_of::<libc::c_int>() as u32) as _;
+    ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut libc::c_int, header.fd);
+
+    loop {
+        let n = libc::sendmsg(socket.as_raw_fd(), &msg, libc::MSG_NOSIGNAL);
+        if n == payload.len() as isize {
+            return Ok(());
+        }
+
+        let err = Error::last_os_error();
+        if n >= 0 {
+            return Err(Error::Os(libc::EMSGSIZE));
+        } else if err != Error::Os(libc::EINTR) {
+            return Err(err);
+        }
+    }
+}
+
+/// Receive a `memfd_secret` allocation sent with `send_secret`.
+///
+/// The data pages are mapped with the same guard pages and canary as the sender's.
+/// The received file descriptor is `O_CLOEXEC`.
+/// Free with `free_memfd_secret`, which unmaps the data without wiping it.
+/// The sender may free its side first, the data then reads as zeros.
+#[inline]
+pub unsafe fn recv_secret(socket: BorrowedFd<'_>) -> Result<NonNull<[u8]>, Error> {
+    recv_secret_with(socket, AllocOptions::new())
+}
+
+/// Receive a `memfd_secret` allocation sent with `send_secret`, with options.
+///
+/// Only `label` and `inheritable` apply, the data pages are set up by the sender.
+pub unsafe fn recv_secret_with(
+    socket: BorrowedFd<'_>,
+    opts: AllocOptions,
+) -> Result<NonNull<[u8]>, Error> {
+    init()?;
+
+    let mut payload = [0u8; size_of::<u64>()];
+    let mut iov = libc::iovec {
+        iov_base: payload.as_mut_ptr() as *mut libc::c_void,
+        iov_len: payload.len(),
+    };
+
+    let mut control: ControlBuf = [0; 4];
+    let mut msg: libc::msghdr = mem::zeroed();
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = libc::CMSG_SPACE(size_of::<libc::c_int>() as u32) as _;
+
+    let n = loop {
+        let n = libc::recvmsg(socket.as_raw_fd(), &mut msg, libc::MSG_CMSG_CLOEXEC);
+        if n >= 0 {
+            break n;
+        }
+
+        let err = Error::last_os_error();
+        if err != Error::Os(libc::EINTR) {
+            return Err(err);
+        }
+    };
+
+    let cmsg = libc::CMSG_FIRSTHDR(&msg);
+    if cmsg.is_null()
+        || (*cmsg).cmsg_level != libc::SOL_SOCKET
+        || (*cmsg).cmsg_type != libc::SCM_RIGHTS
+    {
+        return Err(Error::Os(libc::EBADMSG));
+    }
+    let fd = ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::c_int);
+
+    if n != payload.len() as isize || msg.msg_flags & libc::MSG_CTRUNC != 0 {
+        libc::close(fd);
+        return Err(Error::Os(libc::EBADMSG));
+    }
+
+    let size = u64::from_ne_bytes(payload) as usize;
+    let res = map_secret(fd, size, &opts);
+    if res.is_err() {
+        libc::close(fd);
+    }
+    res
+}
+
+unsafe fn map_secret(
+    fd: libc::c_int,
+    size: usize,
+    opts: &AllocOptions,
+) -> Result<NonNull<[u8]>, Error> {
+    let page_size = rt().page_size;
+    let page_mask = rt().page_mask;
+
+    // only accept `memfd_secret` files, anything else may be readable by others
+    let mut statfs: libc::statfs = mem::zeroed();
+    if libc::fstatfs(fd, &mut statfs) != 0 {
+        return Err(Error::last_os_error());
+    }
+    #[allow(clippy::unnecessary_cast)]
+    if statfs.f_type as libc::c_long != SECRETMEM_MAGIC {
+        return Err(Error::Os(libc::EBADF));
+    }
+
+    let mut stat: libc::stat = mem::zeroed();
+    if libc::fstat(fd, &mut stat) != 0 {
+        return Err(Error::last_os_error());
+    }
+
+    // the file only holds the data pages of the sender
+    let unprotected_size = stat.st_size as usize;
+    if unprotected_size & page_mask != 0 || unprotected_size == 0 {
+        return Err(Error::Os(libc::EBADMSG));
+    }
+    let size_with_canary = size
+        .checked_add(CANARY_SIZE)
+        .filter(|&size_with_canary| page_round(size_with_canary) == unprotected_size)
+        .ok_or(Error::Os(libc::EBADMSG))?;
+
+    let base_ptr = map_data_pages(fd, 0, unprotected_size)?;
+    let unprotected_ptr = base_ptr.add(page_size * 2);
+    let canary_ptr = unprotected_ptr.add(unprotected_size - size_with_canary);
+    let user_ptr = canary_ptr.add(CANARY_SIZE);
+
+    // the canary was written by the sender, keep a private copy to check it on free
+    let mut canary = [0; CANARY_SIZE];
+    ptr::copy_nonoverlapping(canary_ptr, canary.as_mut_ptr(), CANARY_SIZE);
+
+    stats::MEMFD_SECRET.mprotect(base_ptr, page_size, Prot::ReadWrite);
+    let header = Header {
+        unprotected_size,
+        size,
+        fd,
+        flags: FLAG_MEMFD | FLAG_FOREIGN | opts.flags(),
+        offset: 0,
+        counter: 0,
+        canary,
+        label: opts.label,
+        slot: 0,
+    };
+    ptr::write(base_ptr as *mut Header, header);
+    atfork::register(base_ptr);
+    stats::MEMFD_SECRET.mprotect(base_ptr, page_size, Prot::ReadOnly);
+    if let Some(label) = opts.label {
+        label::name_pages(base_ptr, unprotected_size, label);
+    }
+
+    assert_eq!(unprotected_ptr_from_user_ptr(user_ptr), unprotected_ptr);
+
+    stats::MEMFD_SECRET.allocated(&header);
+
+    Ok(NonNull::new_unchecked(ptr::slice_from_raw_parts_mut(
+        user_ptr, size,
+    )))
+}
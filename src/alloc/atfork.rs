@@ -0,0 +1,138 @@
+//! atfork
+//! Keep track of live allocations and drop them in forked children.
+
+extern crate std;
+use self::std::sync::Once;
+use self::std::vec::Vec;
+use super::spin::SpinLock;
+use super::*;
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+/// What happens in a forked child to allocations not marked `inheritable`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AtforkPolicy {
+    /// Wipe and free the allocation.
+    ///
+    /// `memfd_secret` mappings are shared with the parent,
+    /// they are unmapped without being wiped.
+    Free,
+    /// Make the allocation `Prot::NoAccess`, any access faults.
+    NoAccess,
+}
+
+/// Base pointers of all live allocations.
+///
+/// Each allocation keeps its slot in its header, so that it is freed in constant time.
+struct Slots {
+    /// Base pointer of the allocation in each slot, `0` for a free slot.
+    bases: Vec<usize>,
+    free: Vec<usize>,
+}
+
+static REGISTRY: SpinLock<Slots> = SpinLock::new(Slots {
+    bases: Vec::new(),
+    free: Vec::new(),
+});
+static ATFORK_INIT: Once = Once::new();
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+static POLICY: AtomicU8 = AtomicU8::new(0);
+
+const POLICY_FREE: u8 = 1;
+const POLICY_NOACCESS: u8 = 2;
+
+/// Track an allocation and keep its slot in its header,
+/// which must be written and still writable.
+#[inline]
+pub(crate) unsafe fn register(base_ptr: *mut u8) {
+    let slot = REGISTRY.with(|slots| match slots.free.pop() {
+        Some(slot) => {
+            slots.bases[slot] = base_ptr as usize;
+            slot
+        }
+        None => {
+            slots.bases.push(base_ptr as usize);
+            slots.bases.len() - 1
+        }
+    });
+    (*(base_ptr as *mut Header)).slot = slot;
+}
+
+#[inline]
+pub(crate) fn unregister(header: &Header) {
+    REGISTRY.with(|slots| {
+        slots.bases[header.slot] = 0;
+        slots.free.push(header.slot);
+    })
+}
+
+// Both locks are held across `fork`, so that the child finds them consistent.
+
+extern "C" fn prepare() {
+    REGISTRY.lock();
+    stats::LABELS.lock();
+}
+
+extern "C" fn parent() {
+    stats::LABELS.unlock();
+    REGISTRY.unlock();
+}
+
+extern "C" fn child() {
+    // releasing allocations updates the label counters
+    stats::LABELS.unlock();
+
+    let policy = POLICY.load(Ordering::Relaxed);
+    let slots = unsafe { REGISTRY.get() };
+
+    // released slots are not put back on the free list, that could allocate
+    for base in slots.bases.iter_mut().filter(|base| **base != 0) {
+        let base_ptr = *base as *mut u8;
+        let header = unsafe { read_header(base_ptr) };
+
+        // the data pages of a `DontFork` allocation are not mapped in the child
+        if header.flags & (FLAG_INHERITABLE | FLAG_DONTFORK) != 0 {
+            continue;
+        }
+
+        unsafe {
+            match policy {
+                POLICY_NOACCESS => {
+                    let unprotected_ptr = base_ptr.add(rt().page_size * 2);
+                    stats::of(header.flags).mprotect(
+                        unprotected_ptr,
+                        header.unprotected_size,
+                        Prot::NoAccess,
+                    );
+                    continue;
+                }
+                #[cfg(all(feature = "alloc_ext", target_os = "linux"))]
+                _ if header.flags & FLAG_MEMFD != 0 => {
+                    allocext::release_memfd_secret(base_ptr, header, false);
+                }
+                _ => release(base_ptr, header),
+            }
+        }
+        *base = 0;
+    }
+
+    REGISTRY.unlock();
+}
+
+/// Install a `pthread_atfork` handler that applies `policy` in forked children
+/// to every allocation not marked `inheritable`.
+///
+/// Allocations made before the first call are covered too.
+/// The handler is only installed once, later calls just change the policy.
+pub fn install_atfork(policy: AtforkPolicy) -> bool {
+    let policy = match policy {
+        AtforkPolicy::Free => POLICY_FREE,
+        AtforkPolicy::NoAccess => POLICY_NOACCESS,
+    };
+    POLICY.store(policy, Ordering::Relaxed);
+
+    ATFORK_INIT.call_once(|| unsafe {
+        let ok = libc::pthread_atfork(Some(prepare), Some(parent), Some(child)) == 0;
+        INSTALLED.store(ok, Ordering::Relaxed);
+    });
+    INSTALLED.load(Ordering::Relaxed)
+}
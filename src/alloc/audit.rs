@@ -0,0 +1,154 @@
+//! audit
+//! Check in `/proc/self/smaps` that the protections of an allocation took effect.
+
+#![cfg(feature = "std")]
+#![cfg(target_os = "linux")]
+
+extern crate std;
+use self::std::fs;
+use self::std::vec::Vec;
+use super::*;
+use crate::Error;
+
+/// Protections of an allocation, as the kernel reports them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Audit {
+    /// Label given with `AllocOptions::label`.
+    pub label: Option<&'static str>,
+    /// Size of the data pages.
+    pub size: usize,
+    /// Locked bytes of the data pages.
+    ///
+    /// Proportional for pages shared with other processes.
+    pub locked: usize,
+    /// All data pages are `MADV_DONTDUMP` (`dd`).
+    pub dontdump: bool,
+    /// All data pages are `MADV_WIPEONFORK` (`wf`).
+    pub wipeonfork: bool,
+    /// Permissions of the guard pages before and after the data pages.
+    pub guard_pages: [Prot::Ty; 2],
+}
+
+/// A mapping of `/proc/self/smaps`.
+struct Vma {
+    start: usize,
+    end: usize,
+    prot: Prot::Ty,
+    locked: usize,
+    dontdump: bool,
+    wipeonfork: bool,
+}
+
+fn parse_prot(perms: &str) -> Prot::Ty {
+    let perms = perms.as_bytes();
+    let mut prot = Prot::NoAccess;
+    if perms.first() == Some(&b'r') {
+        prot |= libc::PROT_READ;
+    }
+    if perms.get(1) == Some(&b'w') {
+        prot |= libc::PROT_WRITE;
+    }
+    if perms.get(2) == Some(&b'x') {
+        prot |= libc::PROT_EXEC;
+    }
+    prot
+}
+
+fn parse_smaps(smaps: &str) -> Option<Vec<Vma>> {
+    let mut vmas: Vec<Vma> = Vec::new();
+
+    for line in smaps.lines() {
+        let mut fields = line.split_whitespace();
+        let first = match fields.next() {
+            Some(first) => first,
+            None => continue,
+        };
+
+        if !first.ends_with(':') {
+            // `start-end perms offset dev inode [path]`
+            let (start, end) = first.split_once('-')?;
+            vmas.push(Vma {
+                start: usize::from_str_radix(start, 16).ok()?,
+                end: usize::from_str_radix(end, 16).ok()?,
+                prot: parse_prot(fields.next()?),
+                locked: 0,
+                dontdump: false,
+                wipeonfork: false,
+            });
+            continue;
+        }
+
+        let vma = vmas.last_mut()?;
+        match first {
+            "Locked:" => vma.locked = fields.next()?.parse::<usize>().ok()? * 1024,
+            "VmFlags:" => {
+                for flag in fields {
+                    match flag {
+                        "dd" => vma.dontdump = true,
+                        "wf" => vma.wipeonfork = true,
+                        _ => (),
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    Some(vmas)
+}
+
+/// Audit the protections of an allocation in `/proc/self/smaps`.
+///
+/// `mlock` and `madvise` failures are ignored when allocating,
+/// this tells whether they actually took effect.
+pub unsafe fn audit<T: ?Sized>(memptr: NonNull<T>) -> Result<Audit, Error> {
+    let page_size = rt().page_size;
+
+    let unprotected_ptr = unprotected_ptr_from_user_ptr(memptr.as_ptr() as *const u8);
+    let base_ptr = unprotected_ptr.sub(page_size * 2);
+    let header = read_header(base_ptr);
+
+    let smaps = fs::read_to_string("/proc/self/smaps")
+        .map_err(|err| Error::Os(err.raw_os_error().unwrap_or_default()))?;
+    let vmas = parse_smaps(&smaps).ok_or(Error::Os(libc::EINVAL))?;
+
+    let start = unprotected_ptr as usize;
+    let end = start + header.unprotected_size;
+    let guards = [start - page_size, end];
+
+    let mut audit = Audit {
+        label: header.label,
+        size: header.unprotected_size,
+        locked: 0,
+        dontdump: true,
+        wipeonfork: true,
+        guard_pages: [Prot::NoAccess; 2],
+    };
+    let mut covered = 0;
+
+    for vma in &vmas {
+        for (guard, prot) in guards.iter().zip(audit.guard_pages.iter_mut()) {
+            if (vma.start..vma.end).contains(guard) {
+                *prot = vma.prot;
+            }
+        }
+
+        let overlap = end.min(vma.end).saturating_sub(start.max(vma.start));
+        if overlap == 0 {
+            continue;
+        }
+
+        // the mapping may be larger than the allocation, only count its share
+        covered += overlap;
+        audit.locked +=
+            (vma.locked as u128 * overlap as u128 / (vma.end - vma.start) as u128) as usize;
+        audit.dontdump &= vma.dontdump;
+        audit.wipeonfork &= vma.wipeonfork;
+    }
+
+    if covered != header.unprotected_size {
+        return Err(Error::Os(libc::EFAULT));
+    }
+
+    Ok(audit)
+}
@@ -0,0 +1,89 @@
+//! backend
+//! Allocate from the most secure backend available.
+
+use super::*;
+
+/// Allocation backend.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// Guarded and `mlock`ed `malloc`.
+    Malloc,
+    /// Linux `memfd_secret`, the pages are removed from the kernel direct map.
+    MemfdSecret,
+}
+
+#[inline]
+unsafe fn _secret_malloc(size: usize, opts: &AllocOptions) -> Option<(*mut u8, Backend)> {
+    #[cfg(all(feature = "alloc_ext", target_os = "linux"))]
+    {
+        if opts.fork != ForkPolicy::Wipe && allocext::memfd_secret_supported() {
+            if let Ok(memptr) = allocext::_memfd_secret(size, opts) {
+                return Some((memptr, Backend::MemfdSecret));
+            }
+        }
+    }
+
+    _malloc(size, opts)
+        .ok()
+        .map(|memptr| (memptr, Backend::Malloc))
+}
+
+/// Secure `malloc` from the best backend available.
+///
+/// Uses `memfd_secret` when the kernel supports it, the guarded `malloc` otherwise.
+/// Free with `secret_free`.
+#[inline]
+pub unsafe fn secret_malloc<T>() -> Option<(NonNull<T>, Backend)> {
+    secret_malloc_with(AllocOptions::new())
+}
+
+/// Secure `malloc_sized` from the best backend available.
+#[inline]
+pub unsafe fn secret_malloc_sized(size: usize) -> Option<(NonNull<[u8]>, Backend)> {
+    secret_malloc_sized_with(size, AllocOptions::new())
+}
+
+/// Secure `malloc` from the best backend available, with options.
+///
+/// `memfd_secret` can't wipe its shared pages on fork,
+/// `ForkPolicy::Wipe` allocations always come from `malloc`.
+#[inline]
+pub unsafe fn secret_malloc_with<T>(opts: AllocOptions) -> Option<(NonNull<T>, Backend)> {
+    _secret_malloc(mem::size_of::<T>(), &opts).map(|(memptr, backend)| {
+        if opts.prefill() {
+            ptr::write_bytes(memptr, GARBAGE_VALUE, mem::size_of::<T>());
+        }
+        (NonNull::new_unchecked(memptr as *mut T), backend)
+    })
+}
+
+/// Secure `malloc_sized` from the best backend available, with options.
+#[inline]
+pub unsafe fn secret_malloc_sized_with(
+    size: usize,
+    opts: AllocOptions,
+) -> Option<(NonNull<[u8]>, Backend)> {
+    _secret_malloc(size, &opts).map(|(memptr, backend)| {
+        if opts.prefill() {
+            ptr::write_bytes(memptr, GARBAGE_VALUE, size);
+        }
+        (
+            NonNull::new_unchecked(ptr::slice_from_raw_parts_mut(memptr, size)),
+            backend,
+        )
+    })
+}
+
+/// Secure `free` for `secret_malloc` allocations, whatever their backend.
+pub unsafe fn secret_free<T: ?Sized>(memptr: NonNull<T>) {
+    #[cfg(all(feature = "alloc_ext", target_os = "linux"))]
+    {
+        let unprotected_ptr = unprotected_ptr_from_user_ptr(memptr.as_ptr() as *const u8);
+        let base_ptr = unprotected_ptr.sub(rt().page_size * 2);
+        if read_header(base_ptr).flags & FLAG_MEMFD != 0 {
+            return allocext::free_memfd_secret(memptr);
+        }
+    }
+
+    free(memptr)
+}
@@ -0,0 +1,87 @@
+//! canary
+//! Per-allocation canaries, keyed with the process secret.
+//!
+//! `canary = SipHash-2-4-128(key, canary address || counter)`,
+//! so a leaked canary says nothing about the others.
+//! With `insecure_deterministic_canary` the address is left out,
+//! it moves with ASLR and the canaries wouldn't be reproducible.
+
+use super::*;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+#[inline(always)]
+fn sipround(v: &mut [u64; 4]) {
+    v[0] = v[0].wrapping_add(v[1]);
+    v[1] = v[1].rotate_left(13);
+    v[1] ^= v[0];
+    v[0] = v[0].rotate_left(32);
+    v[2] = v[2].wrapping_add(v[3]);
+    v[3] = v[3].rotate_left(16);
+    v[3] ^= v[2];
+    v[0] = v[0].wrapping_add(v[3]);
+    v[3] = v[3].rotate_left(21);
+    v[3] ^= v[0];
+    v[2] = v[2].wrapping_add(v[1]);
+    v[1] = v[1].rotate_left(17);
+    v[1] ^= v[2];
+    v[2] = v[2].rotate_left(32);
+}
+
+/// SipHash-2-4 with 128 bit output, over the two words of `m`.
+fn siphash128(key: &[u8; CANARY_SIZE], m: [u64; 2]) -> [u8; CANARY_SIZE] {
+    let mut k = [0; 8];
+    k.copy_from_slice(&key[..8]);
+    let k0 = u64::from_le_bytes(k);
+    k.copy_from_slice(&key[8..]);
+    let k1 = u64::from_le_bytes(k);
+
+    let mut v = [
+        k0 ^ 0x736f_6d65_7073_6575,
+        k1 ^ 0x646f_7261_6e64_6f6d ^ 0xee,
+        k0 ^ 0x6c79_6765_6e65_7261,
+        k1 ^ 0x7465_6462_7974_6573,
+    ];
+
+    // two message words, then the length block
+    for &mi in m.iter().chain(&[16u64 << 56]) {
+        v[3] ^= mi;
+        sipround(&mut v);
+        sipround(&mut v);
+        v[0] ^= mi;
+    }
+
+    let mut out = [0; CANARY_SIZE];
+    v[2] ^= 0xee;
+    for _ in 0..4 {
+        sipround(&mut v);
+    }
+    out[..8].copy_from_slice(&(v[0] ^ v[1] ^ v[2] ^ v[3]).to_le_bytes());
+    v[1] ^= 0xdd;
+    for _ in 0..4 {
+        sipround(&mut v);
+    }
+    out[8..].copy_from_slice(&(v[0] ^ v[1] ^ v[2] ^ v[3]).to_le_bytes());
+    out
+}
+
+/// The canary expected at `canary_ptr` for an allocation with `counter`.
+#[inline]
+pub(crate) fn derive(canary_ptr: *const u8, counter: u64) -> [u8; CANARY_SIZE] {
+    let address = if cfg!(feature = "insecure_deterministic_canary") {
+        0
+    } else {
+        canary_ptr as usize as u64
+    };
+    siphash128(&rt().canary_key, [address, counter])
+}
+
+/// Write a fresh canary at `canary_ptr`, returns the counter to keep in the header.
+#[inline]
+pub(crate) unsafe fn write(canary_ptr: *mut u8) -> u64 {
+    let counter = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let canary = derive(canary_ptr, counter);
+    ptr::copy_nonoverlapping(canary.as_ptr(), canary_ptr, CANARY_SIZE);
+    counter
+}
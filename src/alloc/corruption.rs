@@ -0,0 +1,96 @@
+//! corruption
+//! What happens when `free` finds a damaged allocation.
+
+#[cfg(feature = "std")]
+extern crate std;
+#[cfg(feature = "std")]
+use self::std::backtrace::{Backtrace, BacktraceStatus};
+use super::*;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+/// What was found damaged.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CorruptionKind {
+    /// The canary in front of the data was overwritten, e.g. by an underflow.
+    Canary,
+    /// The header doesn't match the pointer, or belongs to another allocator.
+    Header,
+    /// The pointer can't come from this allocator.
+    Pointer,
+}
+
+/// Passed to the corruption handler.
+#[non_exhaustive]
+#[derive(Debug)]
+pub struct CorruptionReport<'a> {
+    /// Pointer given to `free`.
+    pub address: *const u8,
+    /// Size requested at allocation, `0` if unknown.
+    pub size: usize,
+    /// Label given with `AllocOptions::label`, `None` if unset or the header can't be trusted.
+    pub label: Option<&'static str>,
+    pub kind: CorruptionKind,
+    /// Captured as configured by `RUST_BACKTRACE` / `RUST_LIB_BACKTRACE`.
+    #[cfg(feature = "std")]
+    pub backtrace: Option<&'a Backtrace>,
+    #[cfg(not(feature = "std"))]
+    _marker: core::marker::PhantomData<&'a ()>,
+}
+
+/// Called on corruption, must not return.
+pub type CorruptionHandler = fn(&CorruptionReport<'_>) -> !;
+
+/// Null for the default handler, which aborts.
+static HANDLER: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+/// Call `handler` instead of aborting when an allocation is found corrupted.
+///
+/// The process memory is in an unknown state at that point:
+/// the handler should only report and terminate, e.g. with `std::process::abort`.
+pub fn set_corruption_handler(handler: CorruptionHandler) {
+    HANDLER.store(handler as *mut (), Ordering::Release);
+}
+
+#[cold]
+#[inline(never)]
+pub(crate) fn corrupted(
+    address: *const u8,
+    size: usize,
+    label: Option<&'static str>,
+    kind: CorruptionKind,
+) -> ! {
+    let handler = HANDLER.load(Ordering::Acquire);
+    if handler.is_null() {
+        abort();
+    }
+
+    let handler = unsafe { mem::transmute::<*mut (), CorruptionHandler>(handler) };
+
+    // only capture for a custom handler, the default one can't use it
+    #[cfg(feature = "std")]
+    {
+        let backtrace = Backtrace::capture();
+        let backtrace = match backtrace.status() {
+            BacktraceStatus::Captured => Some(&backtrace),
+            _ => None,
+        };
+
+        handler(&CorruptionReport {
+            address,
+            size,
+            label,
+            kind,
+            backtrace,
+        })
+    }
+
+    #[cfg(not(feature = "std"))]
+    handler(&CorruptionReport {
+        address,
+        size,
+        label,
+        kind,
+        _marker: core::marker::PhantomData,
+    })
+}
@@ -0,0 +1,57 @@
+//! entropy
+//! Where the canary secret comes from.
+
+use super::*;
+
+/// Fill the buffer with cryptographically secure random bytes.
+pub type EntropySource = fn(&mut [u8]) -> Result<(), Error>;
+
+static SOURCE: OnceCell<EntropySource> = OnceCell::new();
+
+#[cfg(feature = "getrandom")]
+fn default_source(buf: &mut [u8]) -> Result<(), Error> {
+    getrandom::getrandom(buf).map_err(|err| match err.raw_os_error() {
+        Some(errno) => Error::Os(errno),
+        None => Error::Entropy,
+    })
+}
+
+#[cfg(not(any(feature = "getrandom", feature = "custom_entropy")))]
+compile_error!("enable `alloc`, or `custom_entropy` and call `set_entropy_source`");
+
+#[cfg(not(feature = "getrandom"))]
+fn default_source(buf: &mut [u8]) -> Result<(), Error> {
+    let _ = buf;
+    Err(Error::Entropy)
+}
+
+/// Use `source` instead of `getrandom` to draw the canary secret.
+///
+/// For sandboxes where `getrandom` is blocked, or to share an existing CSPRNG.
+/// With `custom_entropy` instead of `alloc`, `init` fails unless a source is set.
+///
+/// `source` runs inside `init` or the first allocation. It must not panic,
+/// the process aborts, nor allocate with memsec, which never returns.
+///
+/// Must be called before `init` and the first allocation,
+/// returns `false` if the entropy source was already used or set.
+pub fn set_entropy_source(source: EntropySource) -> bool {
+    SOURCE.set(source).is_ok()
+}
+
+/// **Insecure**, deterministic entropy source for reproducible fuzzing.
+///
+/// Canaries become predictable, they don't detect anything but accidental overflows.
+/// Never use it outside of tests.
+#[cfg(feature = "insecure_deterministic_canary")]
+pub fn insecure_deterministic_entropy(buf: &mut [u8]) -> Result<(), Error> {
+    for (i, b) in buf.iter_mut().enumerate() {
+        *b = (i as u8).wrapping_mul(0x9d) ^ 0x5a;
+    }
+    Ok(())
+}
+
+#[inline]
+pub(crate) fn fill(buf: &mut [u8]) -> Result<(), Error> {
+    SOURCE.get_or_init(|| default_source)(buf)
+}
@@ -0,0 +1,58 @@
+//! label
+//! Name allocations in `/proc/<pid>/maps` (Linux 5.17+).
+
+use super::*;
+
+#[cfg(target_os = "linux")]
+const PR_SET_VMA: libc::c_int = 0x5356_4d41;
+#[cfg(target_os = "linux")]
+const PR_SET_VMA_ANON_NAME: libc::c_ulong = 0;
+/// Longest name the kernel accepts, without the trailing NUL.
+#[cfg(target_os = "linux")]
+const MAX_NAME_LEN: usize = 79;
+
+#[cfg(target_os = "linux")]
+#[inline]
+unsafe fn set_anon_name(ptr: *mut u8, len: usize, name: *const u8) -> bool {
+    libc::prctl(
+        PR_SET_VMA,
+        PR_SET_VMA_ANON_NAME,
+        ptr as libc::c_ulong,
+        len as libc::c_ulong,
+        name as libc::c_ulong,
+    ) == 0
+}
+
+/// Name the header, guard and data pages of an allocation `[anon:memsec:<label>]`.
+///
+/// Only anonymous pages can be named, `memfd_secret` data pages keep the file name.
+/// Failures are ignored: the kernel may be too old or built without
+/// `CONFIG_ANON_VMA_NAME`, or the label may contain characters it rejects.
+pub(crate) unsafe fn name_pages(base_ptr: *mut u8, unprotected_size: usize, label: &str) {
+    #[cfg(target_os = "linux")]
+    {
+        const PREFIX: &[u8] = b"memsec:";
+
+        let mut name = [0u8; MAX_NAME_LEN + 1];
+        let len = label.len().min(MAX_NAME_LEN - PREFIX.len());
+        name[..PREFIX.len()].copy_from_slice(PREFIX);
+        name[PREFIX.len()..][..len].copy_from_slice(&label.as_bytes()[..len]);
+
+        // one call per run of pages, so that a file backed run doesn't stop the others
+        let page_size = rt().page_size;
+        let data_ptr = base_ptr.add(page_size * 2);
+        set_anon_name(base_ptr, page_size * 2, name.as_ptr());
+        set_anon_name(data_ptr, unprotected_size, name.as_ptr());
+        set_anon_name(data_ptr.add(unprotected_size), page_size, name.as_ptr());
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    let _ = (base_ptr, unprotected_size, label);
+}
+
+/// Label given to an allocation with `AllocOptions::label`.
+pub unsafe fn label<T: ?Sized>(memptr: NonNull<T>) -> Option<&'static str> {
+    let unprotected_ptr = unprotected_ptr_from_user_ptr(memptr.as_ptr() as *const u8);
+    let base_ptr = unprotected_ptr.sub(rt().page_size * 2);
+    read_header(base_ptr).label
+}
@@ -1,66 +1,255 @@
 //! alloc
 
-#![cfg(feature = "alloc")]
+#![cfg(feature = "alloc_base")]
 
 pub mod allocext;
-
+#[cfg(all(unix, feature = "std"))]
+pub mod atfork;
+pub mod audit;
+pub mod backend;
+mod canary;
+pub mod corruption;
+pub mod entropy;
+pub mod label;
+mod once;
+pub mod quota;
+pub mod region;
+pub mod seal;
+#[cfg(feature = "std")]
+mod spin;
+pub mod stats;
+
+// only the `malloc` of other platforms needs `std`
+#[cfg(not(unix))]
 extern crate std;
+use self::corruption::{corrupted, CorruptionKind};
+use self::once::OnceCell;
 use self::raw_alloc::*;
-use self::std::process::abort;
-use self::std::sync::Once;
+use crate::Error;
 use core::mem;
 use core::ptr::{self, NonNull};
-use core::slice;
-use getrandom::getrandom;
 
 const GARBAGE_VALUE: u8 = 0xd0;
 const CANARY_SIZE: usize = 16;
-static ALLOC_INIT: Once = Once::new();
-static mut PAGE_SIZE: usize = 0;
-static mut PAGE_MASK: usize = 0;
-static mut CANARY: [u8; CANARY_SIZE] = [0; CANARY_SIZE];
+
+// -- header --
+
+pub(crate) const FLAG_LOCKED: u32 = 1;
+#[cfg(target_os = "linux")]
+pub(crate) const FLAG_WIPEONFORK: u32 = 1 << 1;
+#[cfg(any(target_os = "linux", all(unix, feature = "std")))]
+pub(crate) const FLAG_DONTFORK: u32 = 1 << 2;
+#[cfg(all(feature = "alloc_ext", target_os = "linux"))]
+pub(crate) const FLAG_MEMFD: u32 = 1 << 3;
+pub(crate) const FLAG_INHERITABLE: u32 = 1 << 4;
+pub(crate) const FLAG_SEALED: u32 = 1 << 6;
+#[cfg(all(feature = "alloc_ext", target_os = "linux"))]
+pub(crate) const FLAG_ARENA: u32 = 1 << 7;
+#[cfg(all(feature = "alloc_ext", target_os = "linux"))]
+pub(crate) const FLAG_FOREIGN: u32 = 1 << 8;
+/// Charged to the quota.
+pub(crate) const FLAG_QUOTA: u32 = 1 << 9;
+/// Only the guard page after the data could be sealed.
+pub(crate) const FLAG_TAIL_SEALED: u32 = 1 << 10;
+
+/// Allocation metadata, stored at the start of the read-only header page.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(crate) struct Header {
+    pub unprotected_size: usize,
+    /// Size asked for by the caller.
+    pub size: usize,
+    /// `memfd_secret` file descriptor, unused by `malloc`.
+    pub fd: i32,
+    pub flags: u32,
+    /// Offset of the data pages in the file of a `SecretArena` allocation,
+    /// `0` for other `memfd_secret` allocations.
+    pub offset: usize,
+    /// Canary counter, mixed with the address into the canary.
+    pub counter: u64,
+    /// Canary of an allocation received from another process, unused otherwise.
+    pub canary: [u8; CANARY_SIZE],
+    /// Label given with `AllocOptions::label`.
+    pub label: Option<&'static str>,
+    /// Slot of the allocation in the `install_atfork` registry.
+    #[cfg(all(unix, feature = "std"))]
+    pub slot: usize,
+}
+
+#[inline]
+pub(crate) unsafe fn read_header(base_ptr: *const u8) -> Header {
+    ptr::read(base_ptr as *const Header)
+}
+
+/// Report corruption if the header is not one of ours
+/// or the canary in front of the user data was overwritten.
+#[inline]
+pub(crate) unsafe fn check_canary(canary_ptr: *const u8, header: &Header) {
+    let memptr = canary_ptr.add(CANARY_SIZE);
+    if header.unprotected_size == 0 || header.unprotected_size & rt().page_mask != 0 {
+        corrupted(memptr, 0, None, CorruptionKind::Header);
+    }
+
+    // the canary of a received allocation was written by the sending process,
+    // which wipes it along with the data if it frees its side first
+    #[cfg(all(feature = "alloc_ext", target_os = "linux"))]
+    let intact = if header.flags & FLAG_FOREIGN != 0 {
+        crate::memeq(canary_ptr, header.canary.as_ptr(), CANARY_SIZE)
+            || crate::memeq(canary_ptr, [0; CANARY_SIZE].as_ptr(), CANARY_SIZE)
+    } else {
+        let expected = canary::derive(canary_ptr, header.counter);
+        crate::memeq(canary_ptr, expected.as_ptr(), CANARY_SIZE)
+    };
+
+    #[cfg(not(all(feature = "alloc_ext", target_os = "linux")))]
+    let intact = {
+        let expected = canary::derive(canary_ptr, header.counter);
+        crate::memeq(canary_ptr, expected.as_ptr(), CANARY_SIZE)
+    };
+
+    if !intact {
+        stats::of(header.flags).canary_failed(header);
+        corrupted(memptr, header.size, header.label, CorruptionKind::Canary);
+    }
+}
 
 // -- alloc init --
 
+/// Process wide state, set once by `init`.
+pub(crate) struct Runtime {
+    pub page_size: usize,
+    pub page_mask: usize,
+    /// Key of the per-allocation canaries.
+    pub canary_key: [u8; CANARY_SIZE],
+}
+
+static RUNTIME: OnceCell<Result<Runtime, Error>> = OnceCell::new();
+static PAGE_SIZE: OnceCell<usize> = OnceCell::new();
+
 #[inline]
-unsafe fn alloc_init() {
+fn os_page_size() -> usize {
     #[cfg(unix)]
     {
-        PAGE_SIZE = libc::sysconf(libc::_SC_PAGESIZE) as usize;
+        unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
     }
 
     #[cfg(windows)]
-    {
+    unsafe {
         let mut si = mem::MaybeUninit::uninit();
         windows_sys::Win32::System::SystemInformation::GetSystemInfo(si.as_mut_ptr());
-        PAGE_SIZE = (*si.as_ptr()).dwPageSize as usize;
+        (*si.as_ptr()).dwPageSize as usize
     }
+}
+
+impl Runtime {
+    fn new() -> Result<Runtime, Error> {
+        let page_size = page_size();
+        if page_size < CANARY_SIZE || page_size < mem::size_of::<Header>() {
+            return Err(Error::PageSize);
+        }
+
+        let mut canary_key = [0; CANARY_SIZE];
+        entropy::fill(&mut canary_key)?;
+
+        Ok(Runtime {
+            page_size,
+            page_mask: page_size - 1,
+            canary_key,
+        })
+    }
+}
+
+/// The runtime of live allocations.
+///
+/// Only call this for allocations, which can't exist unless `init` succeeded.
+#[inline]
+pub(crate) fn rt() -> &'static Runtime {
+    match RUNTIME.get() {
+        Some(Ok(rt)) => rt,
+        _ => abort(),
+    }
+}
 
-    if PAGE_SIZE < CANARY_SIZE || PAGE_SIZE < mem::size_of::<usize>() {
-        panic!("page size too small");
+/// Initialize memsec, like `sodium_init`.
+///
+/// Queries the page size and draws the canary from the entropy source,
+/// `getrandom` unless `set_entropy_source` was called.
+/// This runs once, on the first call or the first allocation, later calls return the same result.
+/// If it fails, allocations fail too.
+pub fn init() -> Result<(), Error> {
+    match RUNTIME.get_or_init(Runtime::new) {
+        Ok(_) => Ok(()),
+        Err(err) => Err(*err),
     }
+}
+
+/// Page size allocations are rounded to.
+///
+/// Unlike `init`, this doesn't draw the canary secret.
+#[inline]
+pub fn page_size() -> usize {
+    *PAGE_SIZE.get_or_init(os_page_size)
+}
 
-    PAGE_MASK = PAGE_SIZE - 1;
+/// Abort the process, without unwinding.
+#[inline]
+pub(crate) fn abort() -> ! {
+    #[cfg(unix)]
+    unsafe {
+        libc::abort()
+    }
 
-    #[allow(static_mut_refs)]
-    getrandom(&mut CANARY).unwrap();
+    #[cfg(not(unix))]
+    std::process::abort()
 }
 
 // -- aligned alloc / aligned free --
 
+#[cfg(unix)]
+mod raw_alloc {
+    use super::*;
+
+    /// Map fresh pages, they are never shared with a heap.
+    #[inline]
+    pub unsafe fn alloc_aligned(size: usize) -> Option<NonNull<u8>> {
+        let memptr = libc::mmap(
+            ptr::null_mut(),
+            size,
+            Prot::ReadWrite,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+            -1,
+            0,
+        );
+
+        if memptr == libc::MAP_FAILED {
+            return None;
+        }
+
+        NonNull::new(memptr as *mut u8)
+    }
+
+    #[inline]
+    pub unsafe fn free_aligned(memptr: *mut u8, size: usize) {
+        if libc::munmap(memptr as *mut libc::c_void, size) != 0 {
+            abort();
+        }
+    }
+}
+
+#[cfg(not(unix))]
 mod raw_alloc {
     use super::std::alloc::{alloc, dealloc, Layout};
     use super::*;
 
     #[inline]
     pub unsafe fn alloc_aligned(size: usize) -> Option<NonNull<u8>> {
-        let layout = Layout::from_size_align_unchecked(size, PAGE_SIZE);
+        let layout = Layout::from_size_align_unchecked(size, rt().page_size);
         NonNull::new(alloc(layout))
     }
 
     #[inline]
     pub unsafe fn free_aligned(memptr: *mut u8, size: usize) {
-        let layout = Layout::from_size_align_unchecked(size, PAGE_SIZE);
+        let layout = Layout::from_size_align_unchecked(size, rt().page_size);
         dealloc(memptr, layout);
     }
 }
@@ -126,78 +315,334 @@ pub unsafe fn mprotect<T: ?Sized>(memptr: NonNull<T>, prot: Prot::Ty) -> bool {
     let memptr = memptr.as_ptr() as *mut u8;
 
     let unprotected_ptr = unprotected_ptr_from_user_ptr(memptr);
-    let base_ptr = unprotected_ptr.sub(PAGE_SIZE * 2);
-    let unprotected_size = ptr::read(base_ptr as *const usize);
-    _mprotect(unprotected_ptr, unprotected_size, prot)
+    let base_ptr = unprotected_ptr.sub(rt().page_size * 2);
+    let header = read_header(base_ptr);
+    stats::of(header.flags).mprotect(unprotected_ptr, header.unprotected_size, prot)
+}
+
+// -- alloc options --
+
+/// What a child process sees of an allocation after `fork()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ForkPolicy {
+    /// The child gets a copy of the allocation.
+    #[default]
+    Inherit,
+    /// `MADV_WIPEONFORK`, the child sees zeros (Linux only).
+    Wipe,
+    /// `MADV_DONTFORK`, the allocation is not mapped in the child (Linux only).
+    DontFork,
+}
+
+/// Allocation options.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AllocOptions {
+    pub(crate) onfault: bool,
+    pub(crate) fork: ForkPolicy,
+    pub(crate) inheritable: bool,
+    pub(crate) seal: bool,
+    pub(crate) cloexec: bool,
+    pub(crate) label: Option<&'static str>,
+}
+
+impl AllocOptions {
+    #[inline]
+    pub const fn new() -> AllocOptions {
+        AllocOptions {
+            onfault: false,
+            fork: ForkPolicy::Inherit,
+            inheritable: false,
+            seal: false,
+            cloexec: true,
+            label: None,
+        }
+    }
+
+    /// Lock pages only once they are touched, using `mlock2(MLOCK_ONFAULT)`.
+    ///
+    /// Untouched pages don't count against `RLIMIT_MEMLOCK`,
+    /// which suits large buffers that only fill gradually.
+    /// The buffer is not prefilled with garbage, so that its pages stay untouched.
+    ///
+    /// Only supported on Linux, elsewhere this falls back to plain `mlock`.
+    #[inline]
+    pub const fn onfault(mut self, onfault: bool) -> AllocOptions {
+        self.onfault = onfault;
+        self
+    }
+
+    /// Set what a forked child sees of the allocation.
+    ///
+    /// The child must neither access nor free a `Wipe` or `DontFork` allocation.
+    /// Only supported on Linux, elsewhere allocations with a policy
+    /// other than `Inherit` fail with `Error::Unsupported`.
+    #[inline]
+    pub const fn fork(mut self, fork: ForkPolicy) -> AllocOptions {
+        self.fork = fork;
+        self
+    }
+
+    /// Keep the allocation in children forked after `install_atfork`.
+    #[inline]
+    pub const fn inheritable(mut self, inheritable: bool) -> AllocOptions {
+        self.inheritable = inheritable;
+        self
+    }
+
+    /// `mseal` the header page and the guard pages,
+    /// so that they can't be `mprotect`ed or unmapped anymore.
+    ///
+    /// Sealed pages stay mapped after `free`.
+    /// Ignored if the kernel doesn't support `mseal`, see `seal_supported`.
+    #[inline]
+    pub const fn seal(mut self, seal: bool) -> AllocOptions {
+        self.seal = seal;
+        self
+    }
+
+    /// Open the `memfd_secret` file descriptor with `O_CLOEXEC`, the default.
+    ///
+    /// Only applies to `memfd_secret` allocations.
+    #[inline]
+    pub const fn cloexec(mut self, cloexec: bool) -> AllocOptions {
+        self.cloexec = cloexec;
+        self
+    }
+
+    /// Label the allocation, e.g. `"tls-session-key"`.
+    ///
+    /// On Linux 5.17+ its anonymous pages show up as `[anon:memsec:<label>]`
+    /// in `/proc/<pid>/maps`, `memfd_secret` pages keep their file name.
+    /// The label is also returned by `label` and counted in `label_stats`.
+    #[inline]
+    pub const fn label(mut self, label: &'static str) -> AllocOptions {
+        self.label = Some(label);
+        self
+    }
+
+    #[cfg(all(feature = "alloc_ext", target_os = "linux"))]
+    #[inline]
+    pub(crate) fn memfd_flags(&self) -> libc::c_int {
+        if self.cloexec {
+            libc::O_CLOEXEC
+        } else {
+            0
+        }
+    }
+
+    #[inline]
+    pub(crate) fn sealed(&self) -> bool {
+        self.seal && seal::seal_supported()
+    }
+
+    #[inline]
+    pub(crate) fn flags(&self) -> u32 {
+        if self.inheritable {
+            FLAG_INHERITABLE
+        } else {
+            0
+        }
+    }
+
+    #[inline]
+    fn prefill(&self) -> bool {
+        !self.onfault
+    }
 }
 
 // -- malloc / free --
 
 #[inline]
-unsafe fn page_round(size: usize) -> usize {
-    (size + PAGE_MASK) & !PAGE_MASK
+pub(crate) unsafe fn page_round(size: usize) -> usize {
+    let page_mask = rt().page_mask;
+    (size + page_mask) & !page_mask
 }
 
 #[inline]
-unsafe fn unprotected_ptr_from_user_ptr(memptr: *const u8) -> *mut u8 {
+pub(crate) unsafe fn unprotected_ptr_from_user_ptr(memptr: *const u8) -> *mut u8 {
+    let rt = rt();
     let canary_ptr = memptr.sub(CANARY_SIZE);
-    let unprotected_ptr_u = canary_ptr as usize & !PAGE_MASK;
-    if unprotected_ptr_u <= PAGE_SIZE * 2 {
-        abort();
+    let unprotected_ptr_u = canary_ptr as usize & !rt.page_mask;
+    if unprotected_ptr_u <= rt.page_size * 2 {
+        corrupted(memptr, 0, None, CorruptionKind::Pointer);
     }
     unprotected_ptr_u as *mut u8
 }
 
-unsafe fn _malloc(size: usize) -> Option<*mut u8> {
-    ALLOC_INIT.call_once(|| alloc_init());
+#[inline]
+unsafe fn lock(ptr: *mut u8, len: usize, opts: &AllocOptions) -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        if opts.onfault {
+            return crate::mlock_onfault(ptr, len);
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    let _ = opts;
 
-    if size >= ::core::usize::MAX - PAGE_SIZE * 4 {
-        return None;
+    crate::mlock(ptr, len)
+}
+
+/// Apply the fork policy, returns the header flags to record.
+#[inline]
+pub(crate) unsafe fn set_fork_policy(
+    ptr: *mut u8,
+    len: usize,
+    fork: ForkPolicy,
+) -> Result<u32, Error> {
+    #[cfg(target_os = "linux")]
+    let (advice, flag) = match fork {
+        ForkPolicy::Inherit => return Ok(0),
+        ForkPolicy::Wipe => (libc::MADV_WIPEONFORK, FLAG_WIPEONFORK),
+        ForkPolicy::DontFork => (libc::MADV_DONTFORK, FLAG_DONTFORK),
+    };
+
+    #[cfg(target_os = "linux")]
+    return match libc::madvise(ptr.cast(), len, advice) {
+        0 => Ok(flag),
+        _ => Err(Error::last_os_error()),
+    };
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (ptr, len);
+        match fork {
+            ForkPolicy::Inherit => Ok(0),
+            _ => Err(Error::Unsupported),
+        }
+    }
+}
+
+unsafe fn _malloc(size: usize, opts: &AllocOptions) -> Result<*mut u8, Error> {
+    init()?;
+    let page_size = rt().page_size;
+
+    if size >= usize::MAX - page_size * 4 {
+        return Err(Error::TooLarge);
     }
 
     // aligned alloc ptr
     let size_with_canary = CANARY_SIZE + size;
     let unprotected_size = page_round(size_with_canary);
-    let total_size = PAGE_SIZE + PAGE_SIZE + unprotected_size + PAGE_SIZE;
-    let base_ptr = alloc_aligned(total_size)?.as_ptr();
-    let unprotected_ptr = base_ptr.add(PAGE_SIZE * 2);
+    let total_size = page_size + page_size + unprotected_size + page_size;
+    quota::reserve(total_size)?;
+
+    let base_ptr = match alloc_aligned(total_size) {
+        Some(base_ptr) => base_ptr.as_ptr(),
+        None => {
+            let err = Error::last_os_error();
+            quota::unreserve(total_size);
+            return Err(err);
+        }
+    };
+    let unprotected_ptr = base_ptr.add(page_size * 2);
+
+    let sealed = opts.sealed();
+    let mut flags = if sealed { FLAG_SEALED } else { 0 };
+    flags |= opts.flags() | FLAG_QUOTA;
+
+    match set_fork_policy(unprotected_ptr, unprotected_size, opts.fork) {
+        Ok(fork_flags) => flags |= fork_flags,
+        Err(err) => {
+            free_aligned(base_ptr, total_size);
+            quota::unreserve(total_size);
+            return Err(err);
+        }
+    }
 
     // mprotect ptr
-    _mprotect(base_ptr.add(PAGE_SIZE), PAGE_SIZE, Prot::NoAccess);
-    _mprotect(
+    stats::MALLOC.mprotect(base_ptr.add(page_size), page_size, Prot::NoAccess);
+    stats::MALLOC.mprotect(
         unprotected_ptr.add(unprotected_size),
-        PAGE_SIZE,
+        page_size,
         Prot::NoAccess,
     );
-    crate::mlock(unprotected_ptr, unprotected_size);
+    if lock(unprotected_ptr, unprotected_size, opts) {
+        flags |= FLAG_LOCKED;
+    } else {
+        stats::MALLOC.mlock_failed();
+    }
 
     let canary_ptr = unprotected_ptr.add(unprotected_size - size_with_canary);
     let user_ptr = canary_ptr.add(CANARY_SIZE);
-    #[allow(static_mut_refs)]
-    ptr::copy_nonoverlapping(CANARY.as_ptr(), canary_ptr, CANARY_SIZE);
-    ptr::write_unaligned(base_ptr as *mut usize, unprotected_size);
-    _mprotect(base_ptr, PAGE_SIZE, Prot::ReadOnly);
+    let counter = canary::write(canary_ptr);
+    let mut header = Header {
+        unprotected_size,
+        size,
+        fd: -1,
+        flags,
+        offset: 0,
+        counter,
+        canary: [0; CANARY_SIZE],
+        label: opts.label,
+        #[cfg(all(unix, feature = "std"))]
+        slot: 0,
+    };
+    ptr::write(base_ptr as *mut Header, header);
+    #[cfg(all(unix, feature = "std"))]
+    atfork::register(base_ptr);
+    stats::MALLOC.mprotect(base_ptr, page_size, Prot::ReadOnly);
+    if let Some(label) = opts.label {
+        label::name_pages(base_ptr, unprotected_size, label);
+    }
+    if sealed {
+        header.flags = seal::seal_guard_pages(base_ptr, unprotected_size);
+    }
 
     assert_eq!(unprotected_ptr_from_user_ptr(user_ptr), unprotected_ptr);
 
-    Some(user_ptr)
+    stats::MALLOC.allocated(&header);
+
+    Ok(user_ptr)
 }
 
 /// Secure `malloc`.
 #[inline]
 pub unsafe fn malloc<T>() -> Option<NonNull<T>> {
-    _malloc(mem::size_of::<T>()).map(|memptr| {
-        ptr::write_bytes(memptr, GARBAGE_VALUE, mem::size_of::<T>());
-        NonNull::new_unchecked(memptr as *mut T)
-    })
+    malloc_with(AllocOptions::new())
 }
 
 /// Secure `malloc_sized`.
 #[inline]
 pub unsafe fn malloc_sized(size: usize) -> Option<NonNull<[u8]>> {
-    _malloc(size).map(|memptr| {
-        ptr::write_bytes(memptr, GARBAGE_VALUE, size);
-        NonNull::new_unchecked(slice::from_raw_parts_mut(memptr, size))
+    malloc_sized_with(size, AllocOptions::new())
+}
+
+/// Secure `malloc` with options.
+#[inline]
+pub unsafe fn malloc_with<T>(opts: AllocOptions) -> Option<NonNull<T>> {
+    try_malloc_with(opts).ok()
+}
+
+/// Secure `malloc_sized` with options.
+#[inline]
+pub unsafe fn malloc_sized_with(size: usize, opts: AllocOptions) -> Option<NonNull<[u8]>> {
+    try_malloc_sized_with(size, opts).ok()
+}
+
+/// Secure `malloc` with options, reports why the allocation failed.
+#[inline]
+pub unsafe fn try_malloc_with<T>(opts: AllocOptions) -> Result<NonNull<T>, Error> {
+    _malloc(mem::size_of::<T>(), &opts).map(|memptr| {
+        if opts.prefill() {
+            ptr::write_bytes(memptr, GARBAGE_VALUE, mem::size_of::<T>());
+        }
+        NonNull::new_unchecked(memptr as *mut T)
+    })
+}
+
+/// Secure `malloc_sized` with options, reports why the allocation failed.
+#[inline]
+pub unsafe fn try_malloc_sized_with(
+    size: usize,
+    opts: AllocOptions,
+) -> Result<NonNull<[u8]>, Error> {
+    _malloc(size, &opts).map(|memptr| {
+        if opts.prefill() {
+            ptr::write_bytes(memptr, GARBAGE_VALUE, size);
+        }
+        NonNull::new_unchecked(ptr::slice_from_raw_parts_mut(memptr, size))
     })
 }
 
@@ -208,20 +653,70 @@ pub unsafe fn free<T: ?Sized>(memptr: NonNull<T>) {
     // get unprotected ptr
     let canary_ptr = memptr.sub(CANARY_SIZE);
     let unprotected_ptr = unprotected_ptr_from_user_ptr(memptr);
-    let base_ptr = unprotected_ptr.sub(PAGE_SIZE * 2);
-    let unprotected_size = ptr::read(base_ptr as *const usize);
+    let base_ptr = unprotected_ptr.sub(rt().page_size * 2);
+    let header = read_header(base_ptr);
 
     // check
-    #[allow(static_mut_refs)]
-    if !crate::memeq(canary_ptr as *const u8, CANARY.as_ptr(), CANARY_SIZE) {
-        abort();
+    check_canary(canary_ptr, &header);
+
+    #[cfg(all(unix, feature = "std"))]
+    atfork::unregister(&header);
+
+    release(base_ptr, header);
+}
+
+/// Wipe and release a `malloc` allocation.
+pub(crate) unsafe fn release(base_ptr: *mut u8, header: Header) {
+    let (ptr, len) = match unprotect_for_release(base_ptr, &header) {
+        Some(range) => range,
+        None => return,
+    };
+
+    let unprotected_ptr = base_ptr.add(rt().page_size * 2);
+    let unprotected_size = header.unprotected_size;
+    if header.flags & FLAG_LOCKED != 0 {
+        crate::munlock(unprotected_ptr, unprotected_size);
+    } else {
+        crate::memzero(unprotected_ptr, unprotected_size);
+        crate::mlock::dodump(unprotected_ptr, unprotected_size);
     }
 
-    // free
-    let total_size = PAGE_SIZE + PAGE_SIZE + unprotected_size + PAGE_SIZE;
-    _mprotect(base_ptr, total_size, Prot::ReadWrite);
+    free_aligned(ptr, len);
+}
 
-    crate::munlock(unprotected_ptr, unprotected_size);
+/// Make an allocation `Prot::ReadWrite` again before it is unmapped,
+/// and take it out of the quota and the counters.
+///
+/// Returns the range to unmap, sealed header and guard pages stay mapped.
+/// `None` for data sealed by `seal_readonly`, which stays mapped and counted.
+pub(crate) unsafe fn unprotect_for_release(
+    base_ptr: *mut u8,
+    header: &Header,
+) -> Option<(*mut u8, usize)> {
+    let page_size = rt().page_size;
+    let stats = stats::of(header.flags);
+
+    let unprotected_ptr = base_ptr.add(page_size * 2);
+    let unprotected_size = header.unprotected_size;
+    let total_size = page_size + page_size + unprotected_size + page_size;
+
+    let sealed = header.flags & FLAG_SEALED != 0;
+    let (ptr, len) = if sealed {
+        (unprotected_ptr, unprotected_size)
+    } else if header.flags & FLAG_TAIL_SEALED != 0 {
+        (base_ptr, total_size - page_size)
+    } else {
+        (base_ptr, total_size)
+    };
+
+    if !stats.mprotect(ptr, len, Prot::ReadWrite) && sealed {
+        return None;
+    }
+
+    stats.released(header);
+    if header.flags & FLAG_QUOTA != 0 {
+        quota::unreserve(total_size);
+    }
 
-    free_aligned(base_ptr, total_size);
+    Some((ptr, len))
 }
@@ -0,0 +1,90 @@
+//! once
+//! `OnceLock` for `no_std`, spins while another thread initializes.
+
+use core::cell::UnsafeCell;
+use core::hint;
+use core::mem::{self, MaybeUninit};
+use core::sync::atomic::{AtomicU8, Ordering};
+
+const UNINIT: u8 = 0;
+const RUNNING: u8 = 1;
+const READY: u8 = 2;
+
+pub(crate) struct OnceCell<T> {
+    state: AtomicU8,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+unsafe impl<T: Send + Sync> Sync for OnceCell<T> {}
+
+/// Dropped only if the initializer unwinds.
+struct AbortOnUnwind;
+
+impl Drop for AbortOnUnwind {
+    fn drop(&mut self) {
+        super::abort()
+    }
+}
+
+impl<T> OnceCell<T> {
+    pub const fn new() -> OnceCell<T> {
+        OnceCell {
+            state: AtomicU8::new(UNINIT),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    #[inline]
+    pub fn get(&self) -> Option<&T> {
+        if self.state.load(Ordering::Acquire) == READY {
+            Some(unsafe { (*self.value.get()).assume_init_ref() })
+        } else {
+            None
+        }
+    }
+
+    /// Run `f` if no other thread got there first, `f` is given back otherwise.
+    ///
+    /// Aborts if `f` panics, other threads would wait forever.
+    fn init_with<F: FnOnce() -> T>(&self, f: F) -> Result<&T, F> {
+        match self
+            .state
+            .compare_exchange(UNINIT, RUNNING, Ordering::Acquire, Ordering::Acquire)
+        {
+            Ok(_) => {
+                let guard = AbortOnUnwind;
+                let value = unsafe { (*self.value.get()).write(f()) };
+                mem::forget(guard);
+                self.state.store(READY, Ordering::Release);
+                Ok(value)
+            }
+            Err(_) => Err(f),
+        }
+    }
+
+    fn wait(&self) -> &T {
+        loop {
+            if let Some(value) = self.get() {
+                return value;
+            }
+            hint::spin_loop();
+        }
+    }
+
+    pub fn get_or_init<F: FnOnce() -> T>(&self, f: F) -> &T {
+        if let Some(value) = self.get() {
+            return value;
+        }
+        match self.init_with(f) {
+            Ok(value) => value,
+            Err(_) => self.wait(),
+        }
+    }
+
+    pub fn set(&self, value: T) -> Result<(), T> {
+        match self.init_with(|| value) {
+            Ok(_) => Ok(()),
+            Err(f) => Err(f()),
+        }
+    }
+}
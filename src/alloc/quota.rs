@@ -0,0 +1,73 @@
+//! quota
+//! Process-wide cap on `malloc` and `memfd_secret` allocations.
+
+use super::*;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Limits on the allocations alive at the same time, `None` for no limit.
+///
+/// Bytes are the pages consumed, with header and guard pages,
+/// as in `BackendStats::mapped_bytes`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Quota {
+    pub max_bytes: Option<usize>,
+    pub max_allocations: Option<usize>,
+}
+
+const UNLIMITED: usize = usize::MAX;
+
+static MAX_BYTES: AtomicUsize = AtomicUsize::new(UNLIMITED);
+static MAX_ALLOCATIONS: AtomicUsize = AtomicUsize::new(UNLIMITED);
+static BYTES: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// Cap the memory used by `malloc` and `memfd_secret`, together.
+///
+/// Once reached, allocations fail with `Error::QuotaExceeded`
+/// until enough are freed. `SecretArena` allocations are bounded by the arena
+/// and received allocations by the sender, neither counts.
+///
+/// A quota below the current use only stops new allocations.
+pub fn set_quota(quota: Quota) {
+    MAX_BYTES.store(quota.max_bytes.unwrap_or(UNLIMITED), Ordering::Relaxed);
+    MAX_ALLOCATIONS.store(
+        quota.max_allocations.unwrap_or(UNLIMITED),
+        Ordering::Relaxed,
+    );
+}
+
+/// The quota set with `set_quota`.
+pub fn quota() -> Quota {
+    let limit = |max: &AtomicUsize| Some(max.load(Ordering::Relaxed)).filter(|&n| n != UNLIMITED);
+    Quota {
+        max_bytes: limit(&MAX_BYTES),
+        max_allocations: limit(&MAX_ALLOCATIONS),
+    }
+}
+
+#[inline]
+fn charge(used: &AtomicUsize, max: &AtomicUsize, n: usize) -> bool {
+    let max = max.load(Ordering::Relaxed);
+    used.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+        used.checked_add(n).filter(|&used| used <= max)
+    })
+    .is_ok()
+}
+
+/// Charge an allocation of `total_size` bytes to the quota.
+pub(crate) fn reserve(total_size: usize) -> Result<(), Error> {
+    if !charge(&ALLOCATIONS, &MAX_ALLOCATIONS, 1) {
+        return Err(Error::QuotaExceeded);
+    }
+    if !charge(&BYTES, &MAX_BYTES, total_size) {
+        ALLOCATIONS.fetch_sub(1, Ordering::Relaxed);
+        return Err(Error::QuotaExceeded);
+    }
+    Ok(())
+}
+
+/// Give back what `reserve` charged.
+pub(crate) fn unreserve(total_size: usize) {
+    ALLOCATIONS.fetch_sub(1, Ordering::Relaxed);
+    BYTES.fetch_sub(total_size, Ordering::Relaxed);
+}
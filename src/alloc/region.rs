@@ -0,0 +1,164 @@
+//! LockedRegion
+//! Lock an existing buffer for the duration of a borrow.
+
+#![cfg(feature = "std")]
+
+extern crate std;
+use self::std::collections::BTreeMap;
+use self::std::sync::Mutex;
+use super::*;
+use core::ops::{Deref, DerefMut};
+
+/// Lock count of every page currently locked by a `LockedRegion`.
+///
+/// `mlock` does not nest, unlocking a page once unlocks it for everyone,
+/// so the pages shared by several regions are only unlocked by the last one.
+static PAGES: Mutex<BTreeMap<usize, usize>> = Mutex::new(BTreeMap::new());
+
+/// A borrowed buffer that stays `mlock`ed until dropped.
+///
+/// On drop, the buffer is wiped and the pages that are no longer used
+/// by any other `LockedRegion` are unlocked.
+///
+/// A region owns the lock state of every page it touches, including the parts
+/// of those pages outside `buf`: once it is dropped they are unlocked and
+/// dumpable again, whoever locked them first. Don't create a region over
+/// memsec allocations, pages locked with `mlock` or while `mlockall` is in effect.
+pub struct LockedRegion<'a> {
+    buf: &'a mut [u8],
+}
+
+/// Call `f` for every run of consecutive pages in `lo..hi` that match `pred`.
+fn for_each_run<P, F>(lo: usize, hi: usize, mut pred: P, mut f: F) -> bool
+where
+    P: FnMut(usize) -> bool,
+    F: FnMut(usize, usize) -> bool,
+{
+    let page_size = page_size();
+    let mut start = None;
+    let mut page = lo;
+
+    while page < hi {
+        match (pred(page), start) {
+            (true, None) => start = Some(page),
+            (false, Some(s)) => {
+                start = None;
+                if !f(s, page) {
+                    return false;
+                }
+            }
+            _ => (),
+        }
+        page += page_size;
+    }
+
+    match start {
+        Some(s) => f(s, hi),
+        None => true,
+    }
+}
+
+impl<'a> LockedRegion<'a> {
+    /// Lock the pages covering `buf`.
+    ///
+    /// Returns `None` if `mlock` fails.
+    pub fn new(buf: &'a mut [u8]) -> Option<LockedRegion<'a>> {
+        if buf.is_empty() {
+            return Some(LockedRegion { buf });
+        }
+
+        let (lo, hi) = page_range(buf);
+        let mut pages = PAGES.lock().unwrap_or_else(|err| err.into_inner());
+
+        let mut locked = 0;
+        let ok = for_each_run(
+            lo,
+            hi,
+            |page| !pages.contains_key(&page),
+            |start, end| {
+                if unsafe { crate::mlock(start as *mut u8, end - start) } {
+                    locked = end;
+                    true
+                } else {
+                    false
+                }
+            },
+        );
+
+        if !ok {
+            // roll back the runs that were locked
+            for_each_run(
+                lo,
+                locked,
+                |page| !pages.contains_key(&page),
+                |start, end| unsafe { crate::munlock_preserve(start as *mut u8, end - start) },
+            );
+            return None;
+        }
+
+        for page in (lo..hi).step_by(page_size()) {
+            *pages.entry(page).or_insert(0) += 1;
+        }
+
+        Some(LockedRegion { buf })
+    }
+}
+
+#[inline]
+fn page_range(buf: &[u8]) -> (usize, usize) {
+    let page_mask = page_size() - 1;
+    let start = buf.as_ptr() as usize;
+    let lo = start & !page_mask;
+    let hi = (start + buf.len() + page_mask) & !page_mask;
+    (lo, hi)
+}
+
+impl Deref for LockedRegion<'_> {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &[u8] {
+        self.buf
+    }
+}
+
+impl DerefMut for LockedRegion<'_> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut [u8] {
+        self.buf
+    }
+}
+
+impl Drop for LockedRegion<'_> {
+    fn drop(&mut self) {
+        unsafe {
+            crate::memzero(self.buf.as_mut_ptr(), self.buf.len());
+        }
+
+        if self.buf.is_empty() {
+            return;
+        }
+
+        let (lo, hi) = page_range(self.buf);
+        let mut pages = PAGES.lock().unwrap_or_else(|err| err.into_inner());
+
+        for_each_run(
+            lo,
+            hi,
+            |page| match pages.get_mut(&page) {
+                Some(n) if *n > 1 => {
+                    *n -= 1;
+                    false
+                }
+                _ => {
+                    pages.remove(&page);
+                    true
+                }
+            },
+            |start, end| {
+                unsafe { crate::munlock_preserve(start as *mut u8, end - start) };
+                true
+            },
+        );
+    }
+}
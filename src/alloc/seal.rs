@@ -0,0 +1,82 @@
+//! mseal
+//! Seal guard pages, header pages and read-only secrets (Linux 6.10+).
+
+use super::once::OnceCell;
+use super::*;
+
+static SUPPORTED: OnceCell<bool> = OnceCell::new();
+
+#[cfg(all(
+    target_os = "linux",
+    target_pointer_width = "64",
+    any(target_arch = "x86_64", target_arch = "aarch64", target_arch = "s390x")
+))]
+#[inline]
+pub(crate) unsafe fn mseal(ptr: *mut u8, len: usize) -> bool {
+    libc::syscall(libc::SYS_mseal, ptr, len, 0) == 0
+}
+
+#[cfg(not(all(
+    target_os = "linux",
+    target_pointer_width = "64",
+    any(target_arch = "x86_64", target_arch = "aarch64", target_arch = "s390x")
+)))]
+#[inline]
+pub(crate) unsafe fn mseal(ptr: *mut u8, len: usize) -> bool {
+    let _ = (ptr, len);
+    false
+}
+
+/// Whether the kernel supports `mseal`.
+///
+/// The result is probed once and cached.
+pub fn seal_supported() -> bool {
+    // sealing an empty range is a no-op on kernels that know `mseal`
+    *SUPPORTED.get_or_init(|| unsafe { mseal(ptr::null_mut(), 0) })
+}
+
+/// Seal the header page and the guard pages of an allocation.
+///
+/// The header must already hold `FLAG_SEALED`, it is cleared if sealing fails.
+/// Sealed pages can't be `mprotect`ed or unmapped anymore,
+/// they stay mapped for the lifetime of the process.
+/// Returns the flags of the allocation.
+pub(crate) unsafe fn seal_guard_pages(base_ptr: *mut u8, unprotected_size: usize) -> u32 {
+    let page_size = rt().page_size;
+    let header = base_ptr as *mut Header;
+
+    // the guard page after the data goes first, the header can't change once sealed
+    let flags = if !mseal(base_ptr.add(page_size * 2 + unprotected_size), page_size) {
+        (*header).flags & !FLAG_SEALED
+    } else if !mseal(base_ptr, page_size * 2) {
+        (*header).flags & !FLAG_SEALED | FLAG_TAIL_SEALED
+    } else {
+        return (*header).flags;
+    };
+
+    let stats = stats::of(flags);
+    stats.mprotect(base_ptr, page_size, Prot::ReadWrite);
+    (*header).flags = flags;
+    stats.mprotect(base_ptr, page_size, Prot::ReadOnly);
+    flags
+}
+
+/// Make an allocation `Prot::ReadOnly` forever.
+///
+/// Only works for allocations made with `AllocOptions::seal`,
+/// returns `false` otherwise or if the kernel doesn't support `mseal`.
+/// Once sealed, the allocation can't be freed, `free` leaves it mapped.
+pub unsafe fn seal_readonly<T: ?Sized>(memptr: NonNull<T>) -> bool {
+    let memptr = memptr.as_ptr() as *mut u8;
+
+    let unprotected_ptr = unprotected_ptr_from_user_ptr(memptr);
+    let base_ptr = unprotected_ptr.sub(rt().page_size * 2);
+    let header = read_header(base_ptr);
+
+    if header.flags & FLAG_SEALED == 0 {
+        return false;
+    }
+
+    stats::of(header.flags).mprotect(unprotected_ptr, header.unprotected_size, Prot::ReadOnly)
+        && mseal(unprotected_ptr, header.unprotected_size)
+}
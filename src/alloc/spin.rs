@@ -0,0 +1,53 @@
+//! spin
+//! A spinlock that can be taken and released from `pthread_atfork` handlers.
+
+use core::cell::UnsafeCell;
+use core::hint;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+pub(crate) struct SpinLock<T> {
+    lock: AtomicBool,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for SpinLock<T> {}
+
+impl<T> SpinLock<T> {
+    pub const fn new(value: T) -> SpinLock<T> {
+        SpinLock {
+            lock: AtomicBool::new(false),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    #[inline]
+    pub fn lock(&self) {
+        while self
+            .lock
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            hint::spin_loop();
+        }
+    }
+
+    #[inline]
+    pub fn unlock(&self) {
+        self.lock.store(false, Ordering::Release);
+    }
+
+    /// The value, the lock must be held.
+    #[allow(clippy::mut_from_ref)]
+    #[inline]
+    pub unsafe fn get(&self) -> &mut T {
+        &mut *self.value.get()
+    }
+
+    #[inline]
+    pub fn with<R, F: FnOnce(&mut T) -> R>(&self, f: F) -> R {
+        self.lock();
+        let ret = f(unsafe { self.get() });
+        self.unlock();
+        ret
+    }
+}
@@ -0,0 +1,211 @@
+//! stats
+//! Process-wide allocation counters.
+
+#[cfg(feature = "std")]
+extern crate std;
+#[cfg(feature = "std")]
+use self::std::collections::BTreeMap;
+#[cfg(feature = "std")]
+use super::spin::SpinLock;
+use super::*;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// Counters of one allocation backend.
+///
+/// Sizes and `live_allocations` describe the allocations alive right now,
+/// the other counters only grow.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BackendStats {
+    pub live_allocations: usize,
+    /// Bytes asked for by the callers.
+    pub requested_bytes: usize,
+    /// Bytes of the pages backing them, with header, guard pages and canary.
+    pub mapped_bytes: usize,
+    /// Bytes of data pages locked in memory with `mlock`.
+    ///
+    /// `memfd_secret` pages can't be swapped without it and are not counted.
+    pub locked_bytes: usize,
+    pub mprotect_calls: u64,
+    pub mlock_failures: u64,
+    /// Canaries found overwritten, before the corruption handler runs.
+    pub canary_failures: u64,
+}
+
+/// Counters of `malloc` and `memfd_secret` allocations.
+///
+/// `SecretArena` and received allocations count as `memfd_secret`.
+/// See `label_stats` for the counters of labeled allocations.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub malloc: BackendStats,
+    pub memfd_secret: BackendStats,
+}
+
+/// Counters of the allocations sharing one label, across backends.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LabelStats {
+    pub live_allocations: usize,
+    /// Bytes asked for by the callers.
+    pub requested_bytes: usize,
+    /// Bytes of the pages backing them, with header, guard pages and canary.
+    pub mapped_bytes: usize,
+    /// Bytes of data pages locked in memory with `mlock`.
+    pub locked_bytes: usize,
+    /// Canaries found overwritten, before the corruption handler runs.
+    pub canary_failures: u64,
+}
+
+/// Counters of labeled allocations.
+///
+/// A spinlock rather than a `Mutex`, the `fork` handlers hold it across `fork`.
+#[cfg(feature = "std")]
+pub(crate) static LABELS: SpinLock<BTreeMap<&'static str, LabelStats>> =
+    SpinLock::new(BTreeMap::new());
+
+/// Update the counters of the label of an allocation, if it has one.
+#[inline]
+fn with_label<F: FnOnce(&mut LabelStats)>(header: &Header, f: F) {
+    #[cfg(feature = "std")]
+    {
+        if let Some(label) = header.label {
+            LABELS.with(|labels| f(labels.entry(label).or_default()));
+        }
+    }
+
+    #[cfg(not(feature = "std"))]
+    let _ = (header, f);
+}
+
+pub(crate) struct Counters {
+    live_allocations: AtomicUsize,
+    requested_bytes: AtomicUsize,
+    mapped_bytes: AtomicUsize,
+    locked_bytes: AtomicUsize,
+    mprotect_calls: AtomicU64,
+    mlock_failures: AtomicU64,
+    canary_failures: AtomicU64,
+}
+
+pub(crate) static MALLOC: Counters = Counters::new();
+pub(crate) static MEMFD_SECRET: Counters = Counters::new();
+
+/// Counters of the backend that made an allocation with these header flags.
+#[inline]
+pub(crate) fn of(flags: u32) -> &'static Counters {
+    #[cfg(all(feature = "alloc_ext", target_os = "linux"))]
+    {
+        if flags & FLAG_MEMFD != 0 {
+            return &MEMFD_SECRET;
+        }
+    }
+
+    #[cfg(not(all(feature = "alloc_ext", target_os = "linux")))]
+    let _ = flags;
+
+    &MALLOC
+}
+
+impl Counters {
+    const fn new() -> Counters {
+        Counters {
+            live_allocations: AtomicUsize::new(0),
+            requested_bytes: AtomicUsize::new(0),
+            mapped_bytes: AtomicUsize::new(0),
+            locked_bytes: AtomicUsize::new(0),
+            mprotect_calls: AtomicU64::new(0),
+            mlock_failures: AtomicU64::new(0),
+            canary_failures: AtomicU64::new(0),
+        }
+    }
+
+    #[inline]
+    fn sizes(header: &Header) -> (usize, usize) {
+        let mapped = header.unprotected_size + rt().page_size * 3;
+        let locked = if header.flags & FLAG_LOCKED != 0 {
+            header.unprotected_size
+        } else {
+            0
+        };
+        (mapped, locked)
+    }
+
+    pub(crate) fn allocated(&self, header: &Header) {
+        let (mapped, locked) = Counters::sizes(header);
+        self.live_allocations.fetch_add(1, Ordering::Relaxed);
+        self.requested_bytes
+            .fetch_add(header.size, Ordering::Relaxed);
+        self.mapped_bytes.fetch_add(mapped, Ordering::Relaxed);
+        self.locked_bytes.fetch_add(locked, Ordering::Relaxed);
+
+        with_label(header, |label| {
+            label.live_allocations += 1;
+            label.requested_bytes += header.size;
+            label.mapped_bytes += mapped;
+            label.locked_bytes += locked;
+        });
+    }
+
+    pub(crate) fn released(&self, header: &Header) {
+        let (mapped, locked) = Counters::sizes(header);
+        self.live_allocations.fetch_sub(1, Ordering::Relaxed);
+        self.requested_bytes
+            .fetch_sub(header.size, Ordering::Relaxed);
+        self.mapped_bytes.fetch_sub(mapped, Ordering::Relaxed);
+        self.locked_bytes.fetch_sub(locked, Ordering::Relaxed);
+
+        with_label(header, |label| {
+            label.live_allocations -= 1;
+            label.requested_bytes -= header.size;
+            label.mapped_bytes -= mapped;
+            label.locked_bytes -= locked;
+        });
+    }
+
+    /// Counted `_mprotect`.
+    #[inline]
+    pub(crate) unsafe fn mprotect(&self, ptr: *mut u8, len: usize, prot: Prot::Ty) -> bool {
+        self.mprotect_calls.fetch_add(1, Ordering::Relaxed);
+        _mprotect(ptr, len, prot)
+    }
+
+    #[inline]
+    pub(crate) fn mlock_failed(&self) {
+        self.mlock_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(crate) fn canary_failed(&self, header: &Header) {
+        self.canary_failures.fetch_add(1, Ordering::Relaxed);
+        with_label(header, |label| label.canary_failures += 1);
+    }
+
+    fn load(&self) -> BackendStats {
+        BackendStats {
+            live_allocations: self.live_allocations.load(Ordering::Relaxed),
+            requested_bytes: self.requested_bytes.load(Ordering::Relaxed),
+            mapped_bytes: self.mapped_bytes.load(Ordering::Relaxed),
+            locked_bytes: self.locked_bytes.load(Ordering::Relaxed),
+            mprotect_calls: self.mprotect_calls.load(Ordering::Relaxed),
+            mlock_failures: self.mlock_failures.load(Ordering::Relaxed),
+            canary_failures: self.canary_failures.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Snapshot of the allocation counters.
+///
+/// Each counter is read atomically, but not all of them at the same instant.
+pub fn stats() -> Stats {
+    Stats {
+        malloc: MALLOC.load(),
+        memfd_secret: MEMFD_SECRET.load(),
+    }
+}
+
+/// Snapshot of the counters of labeled allocations, by label.
+///
+/// Labels stay listed once their allocations are freed.
+#[cfg(feature = "std")]
+pub fn label_stats() -> BTreeMap<&'static str, LabelStats> {
+    LABELS.with(|labels| labels.clone())
+}
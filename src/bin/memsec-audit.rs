@@ -0,0 +1,194 @@
+//! memsec-audit
+//! Report the host settings that decide whether secrets can leak out of memory.
+//!
+//! Usage: `memsec-audit [--json]`
+
+#[cfg(target_os = "linux")]
+mod audit {
+    use memsec::probe::{self, SwapDevice};
+    use std::fmt::Write;
+
+    pub struct Report {
+        memlock_limit: Option<(u64, u64)>,
+        memfd_secret: bool,
+        core_pattern: Option<String>,
+        core_limit: Option<(u64, u64)>,
+        ptrace_scope: Option<u32>,
+        swap_devices: Option<Vec<SwapDevice>>,
+        transparent_hugepages: Option<String>,
+    }
+
+    impl Report {
+        pub fn probe() -> Report {
+            Report {
+                memlock_limit: memsec::memlock_limit(),
+                memfd_secret: probe::memfd_secret_supported(),
+                core_pattern: probe::core_pattern(),
+                core_limit: probe::core_limit(),
+                ptrace_scope: probe::ptrace_scope(),
+                swap_devices: probe::swap_devices(),
+                transparent_hugepages: probe::transparent_hugepages(),
+            }
+        }
+
+        pub fn text(&self) -> String {
+            fn limit(n: u64) -> String {
+                if n == u64::MAX {
+                    "unlimited".into()
+                } else {
+                    n.to_string()
+                }
+            }
+            fn limits(l: Option<(u64, u64)>) -> String {
+                match l {
+                    Some((soft, hard)) => format!("soft {}, hard {}", limit(soft), limit(hard)),
+                    None => "unknown".into(),
+                }
+            }
+
+            let mut out = String::new();
+            let _ = writeln!(out, "RLIMIT_MEMLOCK         {}", limits(self.memlock_limit));
+            let _ = writeln!(
+                out,
+                "memfd_secret           {}",
+                if self.memfd_secret {
+                    "supported"
+                } else {
+                    "unavailable"
+                }
+            );
+            let _ = writeln!(
+                out,
+                "core_pattern           {}",
+                self.core_pattern.as_deref().unwrap_or("unknown")
+            );
+            let _ = writeln!(out, "RLIMIT_CORE            {}", limits(self.core_limit));
+            let _ = writeln!(
+                out,
+                "ptrace_scope           {}",
+                match self.ptrace_scope {
+                    Some(scope) => scope.to_string(),
+                    None => "unavailable (no Yama)".into(),
+                }
+            );
+            match &self.swap_devices {
+                Some(devices) if devices.is_empty() => {
+                    let _ = writeln!(out, "swap                   none");
+                }
+                Some(devices) => {
+                    for (i, device) in devices.iter().enumerate() {
+                        let _ = writeln!(
+                            out,
+                            "{:<23}{} ({})",
+                            if i == 0 { "swap" } else { "" },
+                            device.path,
+                            if device.encrypted {
+                                "encrypted"
+                            } else {
+                                "not encrypted"
+                            }
+                        );
+                    }
+                }
+                None => {
+                    let _ = writeln!(out, "swap                   unknown");
+                }
+            }
+            let _ = writeln!(
+                out,
+                "transparent hugepages  {}",
+                self.transparent_hugepages.as_deref().unwrap_or("unknown")
+            );
+            out
+        }
+
+        pub fn json(&self) -> String {
+            fn string(s: &str) -> String {
+                let mut out = String::from("\"");
+                for c in s.chars() {
+                    match c {
+                        '"' => out.push_str("\\\""),
+                        '\\' => out.push_str("\\\\"),
+                        c if (c as u32) < 0x20 => {
+                            let _ = write!(out, "\\u{:04x}", c as u32);
+                        }
+                        c => out.push(c),
+                    }
+                }
+                out.push('"');
+                out
+            }
+            fn or_null<T, F: FnOnce(T) -> String>(v: Option<T>, f: F) -> String {
+                v.map_or_else(|| "null".into(), f)
+            }
+            fn limits(l: Option<(u64, u64)>) -> String {
+                let limit = |n: u64| {
+                    if n == u64::MAX {
+                        string("unlimited")
+                    } else {
+                        n.to_string()
+                    }
+                };
+                or_null(l, |(soft, hard)| {
+                    format!("{{\"soft\":{},\"hard\":{}}}", limit(soft), limit(hard))
+                })
+            }
+
+            let swap_devices = or_null(self.swap_devices.as_ref(), |devices| {
+                let devices: Vec<String> = devices
+                    .iter()
+                    .map(|device| {
+                        format!(
+                            "{{\"path\":{},\"encrypted\":{}}}",
+                            string(&device.path),
+                            device.encrypted
+                        )
+                    })
+                    .collect();
+                format!("[{}]", devices.join(","))
+            });
+
+            format!(
+                "{{\"memlock_limit\":{},\"memfd_secret\":{},\"core_pattern\":{},\"core_limit\":{},\"ptrace_scope\":{},\"swap_devices\":{},\"transparent_hugepages\":{}}}",
+                limits(self.memlock_limit),
+                self.memfd_secret,
+                or_null(self.core_pattern.as_deref(), string),
+                limits(self.core_limit),
+                or_null(self.ptrace_scope, |scope| scope.to_string()),
+                swap_devices,
+                or_null(self.transparent_hugepages.as_deref(), string),
+            )
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn main() {
+    let mut json = false;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--json" => json = true,
+            "-h" | "--help" => {
+                println!("Usage: memsec-audit [--json]");
+                return;
+            }
+            _ => {
+                eprintln!("memsec-audit: unknown argument `{}`", arg);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    let report = audit::Report::probe();
+    if json {
+        println!("{}", report.json());
+    } else {
+        print!("{}", report.text());
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("memsec-audit only supports Linux");
+    std::process::exit(1);
+}
@@ -0,0 +1,131 @@
+//! error
+
+#[cfg(any(feature = "std", all(feature = "alloc_base", not(unix))))]
+extern crate std;
+
+use core::fmt;
+
+/// memsec error.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// A system call failed with this `errno`.
+    Os(i32),
+    /// The requested size is too large.
+    TooLarge,
+    /// The page size is too small for the allocation layout.
+    PageSize,
+    /// The OS random source failed without an `errno`.
+    Entropy,
+    /// The limit set with `set_quota` was reached.
+    QuotaExceeded,
+    /// The option is not supported on this platform.
+    Unsupported,
+}
+
+impl Error {
+    #[cfg(feature = "use_os")]
+    #[cfg(unix)]
+    #[inline]
+    pub(crate) fn last_os_error() -> Error {
+        Error::Os(errno::errno())
+    }
+
+    #[cfg(feature = "alloc_base")]
+    #[cfg(not(unix))]
+    #[inline]
+    pub(crate) fn last_os_error() -> Error {
+        Error::Os(
+            std::io::Error::last_os_error()
+                .raw_os_error()
+                .unwrap_or_default(),
+        )
+    }
+}
+
+#[cfg(feature = "use_os")]
+#[cfg(unix)]
+mod errno {
+    #[cfg(any(target_os = "linux", target_os = "emscripten", target_os = "fuchsia"))]
+    use libc::__errno_location as errno_location;
+
+    #[cfg(any(target_os = "android", target_os = "netbsd", target_os = "openbsd"))]
+    use libc::__errno as errno_location;
+
+    #[cfg(any(
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "tvos",
+        target_os = "watchos",
+        target_os = "freebsd",
+        target_os = "dragonfly"
+    ))]
+    use libc::__error as errno_location;
+
+    #[cfg(any(target_os = "solaris", target_os = "illumos"))]
+    use libc::___errno as errno_location;
+
+    #[cfg(target_os = "haiku")]
+    use libc::_errnop as errno_location;
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "emscripten",
+        target_os = "fuchsia",
+        target_os = "android",
+        target_os = "netbsd",
+        target_os = "openbsd",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "tvos",
+        target_os = "watchos",
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "solaris",
+        target_os = "illumos",
+        target_os = "haiku"
+    ))]
+    #[inline]
+    pub(super) fn errno() -> i32 {
+        unsafe { *errno_location() }
+    }
+
+    // where errno lives is unknown on other targets
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "emscripten",
+        target_os = "fuchsia",
+        target_os = "android",
+        target_os = "netbsd",
+        target_os = "openbsd",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "tvos",
+        target_os = "watchos",
+        target_os = "freebsd",
+        target_os = "dragonfly",
+        target_os = "solaris",
+        target_os = "illumos",
+        target_os = "haiku"
+    )))]
+    #[inline]
+    pub(super) fn errno() -> i32 {
+        0
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Os(errno) => write!(f, "os error {}", errno),
+            Error::TooLarge => f.write_str("size too large"),
+            Error::PageSize => f.write_str("page size too small"),
+            Error::Entropy => f.write_str("random source unavailable"),
+            Error::QuotaExceeded => f.write_str("secure memory quota exceeded"),
+            Error::Unsupported => f.write_str("unsupported on this platform"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
@@ -0,0 +1,56 @@
+//! harden
+//! Process wide protections: no core dumps, no `ptrace` by same-user processes.
+
+#![cfg(feature = "use_os")]
+#![cfg(unix)]
+
+use crate::Error;
+
+/// Clear the dumpable attribute with `PR_SET_DUMPABLE`.
+///
+/// The process doesn't dump core anymore, and only `CAP_SYS_PTRACE`
+/// can attach to it or read its `/proc/<pid>/mem`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn set_undumpable() -> Result<(), Error> {
+    if unsafe { libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0) } != 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Set `RLIMIT_CORE` to 0, soft and hard limit.
+///
+/// Children inherit the limit.
+pub fn disable_core_dumps() -> Result<(), Error> {
+    let limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    if unsafe { libc::setrlimit(libc::RLIMIT_CORE, &limit) } != 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Set `PR_SET_NO_NEW_PRIVS`, `execve` can't grant privileges anymore.
+///
+/// This can't be undone, and children inherit it.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn set_no_new_privs() -> Result<(), Error> {
+    if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// `set_undumpable` and `disable_core_dumps`,
+/// and `set_no_new_privs` if `no_new_privs` is set.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn apply(no_new_privs: bool) -> Result<(), Error> {
+    set_undumpable()?;
+    disable_core_dumps()?;
+    if no_new_privs {
+        set_no_new_privs()?;
+    }
+    Ok(())
+}
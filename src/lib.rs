@@ -4,19 +4,89 @@
 #![allow(clippy::missing_safety_doc)]
 
 mod alloc;
+mod error;
+pub mod harden;
 mod mlock;
+pub mod probe;
 
 use core::ptr;
 
+pub use error::Error;
+
+#[cfg(feature = "use_os")]
+pub use mlock::{
+    locked_bytes, mlock, mlockall, munlock, munlock_preserve, munlockall, MlockAllFlags,
+};
+
+#[cfg(feature = "use_os")]
+#[cfg(unix)]
+pub use mlock::{memlock_limit, raise_memlock_limit};
+
 #[cfg(feature = "use_os")]
-pub use mlock::{mlock, munlock};
+#[cfg(target_os = "linux")]
+pub use mlock::mlock_onfault;
+
+#[cfg(feature = "alloc_base")]
+pub use alloc::{
+    free, init, malloc, malloc_sized, malloc_sized_with, malloc_with, mprotect, page_size,
+    try_malloc_sized_with, try_malloc_with, AllocOptions, ForkPolicy, Prot,
+};
+
+#[cfg(feature = "alloc_base")]
+pub use alloc::backend::{
+    secret_free, secret_malloc, secret_malloc_sized, secret_malloc_sized_with, secret_malloc_with,
+    Backend,
+};
+
+#[cfg(feature = "alloc_base")]
+#[cfg(feature = "std")]
+#[cfg(target_os = "linux")]
+pub use alloc::audit::{audit, Audit};
+
+#[cfg(feature = "alloc_base")]
+pub use alloc::corruption::{
+    set_corruption_handler, CorruptionHandler, CorruptionKind, CorruptionReport,
+};
+
+#[cfg(feature = "alloc_base")]
+pub use alloc::entropy::{set_entropy_source, EntropySource};
+
+#[cfg(feature = "alloc_base")]
+#[cfg(feature = "insecure_deterministic_canary")]
+pub use alloc::entropy::insecure_deterministic_entropy;
+
+#[cfg(feature = "alloc_base")]
+pub use alloc::label::label;
+
+#[cfg(feature = "alloc_base")]
+pub use alloc::quota::{quota, set_quota, Quota};
+
+#[cfg(feature = "alloc_base")]
+#[cfg(feature = "std")]
+pub use alloc::region::LockedRegion;
+
+#[cfg(feature = "alloc_base")]
+pub use alloc::stats::{stats, BackendStats, LabelStats, Stats};
+
+#[cfg(feature = "alloc_base")]
+#[cfg(feature = "std")]
+pub use alloc::stats::label_stats;
+
+#[cfg(feature = "alloc_base")]
+pub use alloc::seal::{seal_readonly, seal_supported};
 
-#[cfg(feature = "alloc")]
-pub use alloc::{free, malloc, malloc_sized, mprotect, Prot};
+#[cfg(feature = "alloc_base")]
+#[cfg(feature = "std")]
+#[cfg(unix)]
+pub use alloc::atfork::{install_atfork, AtforkPolicy};
 
 #[cfg(feature = "alloc_ext")]
 #[cfg(target_os = "linux")]
-pub use alloc::allocext::{free_memfd_secret, memfd_secret, memfd_secret_sized};
+pub use alloc::allocext::{
+    free_memfd_secret, memfd_secret, memfd_secret_as_fd, memfd_secret_sized,
+    memfd_secret_supported, recv_secret, recv_secret_with, send_secret,
+    try_memfd_secret_sized_with, try_memfd_secret_with, SecretArena, SecretFile,
+};
 
 // -- memcmp --
 
@@ -2,48 +2,264 @@
 
 #![cfg(feature = "use_os")]
 
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+static LOCKED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+#[inline]
+pub(crate) unsafe fn dontdump(addr: *mut u8, len: usize) {
+    #[cfg(target_os = "linux")]
+    libc::madvise(addr as *mut libc::c_void, len, libc::MADV_DONTDUMP);
+
+    #[cfg(any(target_os = "freebsd", target_os = "dragonfly"))]
+    libc::madvise(addr as *mut libc::c_void, len, libc::MADV_NOCORE);
+
+    #[cfg(not(any(target_os = "linux", target_os = "freebsd", target_os = "dragonfly")))]
+    let _ = (addr, len);
+}
+
+#[inline]
+pub(crate) unsafe fn dodump(addr: *mut u8, len: usize) {
+    #[cfg(target_os = "linux")]
+    libc::madvise(addr as *mut libc::c_void, len, libc::MADV_DODUMP);
+
+    #[cfg(any(target_os = "freebsd", target_os = "dragonfly"))]
+    libc::madvise(addr as *mut libc::c_void, len, libc::MADV_CORE);
+
+    #[cfg(not(any(target_os = "linux", target_os = "freebsd", target_os = "dragonfly")))]
+    let _ = (addr, len);
+}
+
+#[inline]
+fn account_lock(len: usize) {
+    LOCKED_BYTES.fetch_add(len, Ordering::Relaxed);
+}
+
+#[inline]
+fn account_unlock(len: usize) {
+    let _ = LOCKED_BYTES.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| {
+        Some(n.saturating_sub(len))
+    });
+}
+
 /// Cross-platform `mlock`.
 ///
 /// * Unix `mlock`.
 /// * Windows `VirtualLock`.
 pub unsafe fn mlock(addr: *mut u8, len: usize) -> bool {
+    dontdump(addr, len);
+
     #[cfg(unix)]
-    {
-        #[cfg(target_os = "linux")]
-        libc::madvise(addr as *mut libc::c_void, len, libc::MADV_DONTDUMP);
+    let ok = libc::mlock(addr as *mut libc::c_void, len) == 0;
 
-        #[cfg(any(target_os = "freebsd", target_os = "dragonfly"))]
-        libc::madvise(addr as *mut libc::c_void, len, libc::MADV_NOCORE);
+    #[cfg(windows)]
+    let ok = windows_sys::Win32::System::Memory::VirtualLock(addr.cast(), len) != 0;
 
-        libc::mlock(addr as *mut libc::c_void, len) == 0
+    if ok {
+        account_lock(len);
     }
+    ok
+}
 
-    #[cfg(windows)]
-    {
-        windows_sys::Win32::System::Memory::VirtualLock(addr.cast(), len) != 0
+/// Linux `mlock2` with `MLOCK_ONFAULT`.
+///
+/// Pages are only locked once they are faulted in,
+/// so untouched pages do not count against `RLIMIT_MEMLOCK`.
+#[cfg(target_os = "linux")]
+pub unsafe fn mlock_onfault(addr: *mut u8, len: usize) -> bool {
+    dontdump(addr, len);
+
+    let ok = libc::syscall(
+        libc::SYS_mlock2,
+        addr as *mut libc::c_void,
+        len,
+        libc::MLOCK_ONFAULT,
+    ) == 0;
+
+    if ok {
+        account_lock(len);
     }
+    ok
 }
 
 /// Cross-platform `munlock`.
 ///
 /// * Unix `munlock`.
 /// * Windows `VirtualUnlock`.
+///
+/// The range is zeroed before it is unlocked.
 pub unsafe fn munlock(addr: *mut u8, len: usize) -> bool {
     crate::memzero(addr, len);
 
+    munlock_preserve(addr, len)
+}
+
+/// Cross-platform `munlock` that keeps the contents of the range.
+///
+/// Only reverses what `mlock` did, the caller is responsible for wiping the range.
+pub unsafe fn munlock_preserve(addr: *mut u8, len: usize) -> bool {
+    dodump(addr, len);
+
+    #[cfg(unix)]
+    let ok = libc::munlock(addr as *mut libc::c_void, len) == 0;
+
+    #[cfg(windows)]
+    let ok = windows_sys::Win32::System::Memory::VirtualUnlock(addr.cast(), len) != 0;
+
+    if ok {
+        account_unlock(len);
+    }
+    ok
+}
+
+/// Number of bytes currently locked through memsec.
+///
+/// Counts every successful `mlock` (including the ones done by the secure allocator)
+/// that has not been undone by `munlock` yet.
+pub fn locked_bytes() -> usize {
+    LOCKED_BYTES.load(Ordering::Relaxed)
+}
+
+/// `RLIMIT_MEMLOCK` soft and hard limits, in bytes.
+///
+/// `u64::MAX` means unlimited.
+#[cfg(unix)]
+pub fn memlock_limit() -> Option<(u64, u64)> {
+    let mut rlim = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+
+    if unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut rlim) } != 0 {
+        return None;
+    }
+
+    Some(rlimit_pair(&rlim))
+}
+
+/// Soft and hard limits of a `getrlimit` result, `u64::MAX` means unlimited.
+#[cfg(unix)]
+#[allow(clippy::unnecessary_cast)]
+pub(crate) fn rlimit_pair(rlim: &libc::rlimit) -> (u64, u64) {
+    let conv = |n: libc::rlim_t| {
+        if n == libc::RLIM_INFINITY {
+            u64::MAX
+        } else {
+            n as u64
+        }
+    };
+    (conv(rlim.rlim_cur), conv(rlim.rlim_max))
+}
+
+/// Raise the `RLIMIT_MEMLOCK` soft limit up to the hard limit.
+#[cfg(unix)]
+pub fn raise_memlock_limit() -> bool {
+    let mut rlim = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+
+    unsafe {
+        if libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut rlim) != 0 {
+            return false;
+        }
+
+        if rlim.rlim_cur == rlim.rlim_max {
+            return true;
+        }
+
+        rlim.rlim_cur = rlim.rlim_max;
+        libc::setrlimit(libc::RLIMIT_MEMLOCK, &rlim) == 0
+    }
+}
+
+/// `mlockall` flags.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MlockAllFlags(u8);
+
+impl MlockAllFlags {
+    /// Lock all pages currently mapped.
+    pub const CURRENT: MlockAllFlags = MlockAllFlags(1);
+    /// Lock all pages that become mapped in the future.
+    pub const FUTURE: MlockAllFlags = MlockAllFlags(1 << 1);
+    /// Only lock pages once they are faulted in (Linux only).
+    pub const ONFAULT: MlockAllFlags = MlockAllFlags(1 << 2);
+
+    #[inline]
+    pub const fn empty() -> MlockAllFlags {
+        MlockAllFlags(0)
+    }
+
+    #[inline]
+    pub const fn contains(self, other: MlockAllFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl core::ops::BitOr for MlockAllFlags {
+    type Output = MlockAllFlags;
+
+    #[inline]
+    fn bitor(self, rhs: MlockAllFlags) -> MlockAllFlags {
+        MlockAllFlags(self.0 | rhs.0)
+    }
+}
+
+impl core::ops::BitOrAssign for MlockAllFlags {
+    #[inline]
+    fn bitor_assign(&mut self, rhs: MlockAllFlags) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// Cross-platform `mlockall`.
+///
+/// * Unix `mlockall`, macOS only has a stub that always fails.
+/// * Windows is not supported, always returns `false`.
+///
+/// Pages locked this way are not counted by `locked_bytes`.
+pub unsafe fn mlockall(flags: MlockAllFlags) -> bool {
     #[cfg(unix)]
     {
-        #[cfg(target_os = "linux")]
-        libc::madvise(addr as *mut libc::c_void, len, libc::MADV_DODUMP);
+        let mut raw = 0;
+        if flags.contains(MlockAllFlags::CURRENT) {
+            raw |= libc::MCL_CURRENT;
+        }
+        if flags.contains(MlockAllFlags::FUTURE) {
+            raw |= libc::MCL_FUTURE;
+        }
+        if flags.contains(MlockAllFlags::ONFAULT) {
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            {
+                raw |= libc::MCL_ONFAULT;
+            }
 
-        #[cfg(any(target_os = "freebsd", target_os = "dragonfly"))]
-        libc::madvise(addr as *mut libc::c_void, len, libc::MADV_CORE);
+            #[cfg(not(any(target_os = "linux", target_os = "android")))]
+            return false;
+        }
 
-        libc::munlock(addr as *mut libc::c_void, len) == 0
+        libc::mlockall(raw) == 0
+    }
+
+    #[cfg(windows)]
+    {
+        let _ = flags;
+        false
+    }
+}
+
+/// Cross-platform `munlockall`.
+///
+/// * Unix `munlockall`.
+/// * Windows is not supported, always returns `false`.
+pub unsafe fn munlockall() -> bool {
+    #[cfg(unix)]
+    {
+        libc::munlockall() == 0
     }
 
     #[cfg(windows)]
     {
-        windows_sys::Win32::System::Memory::VirtualUnlock(addr.cast(), len) != 0
+        false
     }
 }
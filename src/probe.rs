@@ -0,0 +1,159 @@
+//! probe
+//! Host settings that decide whether secrets can leak out of memory (Linux).
+
+#![cfg(feature = "alloc_base")]
+#![cfg(feature = "std")]
+#![cfg(target_os = "linux")]
+
+extern crate std;
+use self::std::fs;
+use self::std::os::unix::fs::{FileTypeExt, MetadataExt};
+use self::std::path::Path;
+use self::std::string::String;
+use self::std::vec::Vec;
+
+/// An active swap device or file, from `/proc/swaps`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SwapDevice {
+    pub path: String,
+    /// The swap sits on a dm-crypt device, directly or through other device mapper targets.
+    pub encrypted: bool,
+}
+
+#[inline]
+fn read_trimmed<P: AsRef<Path>>(path: P) -> Option<String> {
+    fs::read_to_string(path).ok().map(|s| s.trim_end().into())
+}
+
+/// Whether the kernel supports `memfd_secret`.
+///
+/// It may be missing (`ENOSYS`) or disabled with `secretmem.enable=0`.
+/// Probed on every call, `memfd_secret_supported` with `alloc_ext` caches it.
+pub fn memfd_secret_supported() -> bool {
+    let fd = unsafe { libc::syscall(libc::SYS_memfd_secret, libc::O_CLOEXEC) };
+    if fd < 0 {
+        return false;
+    }
+    unsafe { libc::close(fd as libc::c_int) };
+    true
+}
+
+/// `/proc/sys/kernel/core_pattern`.
+pub fn core_pattern() -> Option<String> {
+    read_trimmed("/proc/sys/kernel/core_pattern")
+}
+
+/// `RLIMIT_CORE` soft and hard limits, in bytes.
+///
+/// `u64::MAX` means unlimited.
+pub fn core_limit() -> Option<(u64, u64)> {
+    let mut rlim = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+
+    if unsafe { libc::getrlimit(libc::RLIMIT_CORE, &mut rlim) } != 0 {
+        return None;
+    }
+
+    Some(crate::mlock::rlimit_pair(&rlim))
+}
+
+/// Yama `ptrace_scope`, `None` without Yama.
+///
+/// `0` lets any same-user process `ptrace` this one.
+pub fn ptrace_scope() -> Option<u32> {
+    read_trimmed("/proc/sys/kernel/yama/ptrace_scope")?
+        .parse()
+        .ok()
+}
+
+/// Transparent hugepages mode: `always`, `madvise` or `never`.
+pub fn transparent_hugepages() -> Option<String> {
+    let enabled = read_trimmed("/sys/kernel/mm/transparent_hugepage/enabled")?;
+    let start = enabled.find('[')? + 1;
+    let end = start + enabled[start..].find(']')?;
+    Some(enabled[start..end].into())
+}
+
+/// Active swap devices and files.
+pub fn swap_devices() -> Option<Vec<SwapDevice>> {
+    let swaps = fs::read_to_string("/proc/swaps").ok()?;
+
+    let devices = swaps
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.split_whitespace().next())
+        .map(|path| {
+            let path = unescape(path);
+            let encrypted = swap_encrypted(&path);
+            SwapDevice { path, encrypted }
+        })
+        .collect();
+    Some(devices)
+}
+
+/// Undo the octal escapes of `/proc/swaps`, e.g. `\040` for a space.
+fn unescape(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let escape = bytes.get(i + 1..i + 4).filter(|_| bytes[i] == b'\\');
+        match escape.and_then(|oct| u8::from_str_radix(std::str::from_utf8(oct).ok()?, 8).ok()) {
+            Some(b) => {
+                out.push(b);
+                i += 4;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn swap_encrypted(path: &str) -> bool {
+    let meta = match fs::metadata(path) {
+        Ok(meta) => meta,
+        Err(_) => return false,
+    };
+
+    // a swap partition is the device itself, a swap file lives on one
+    let dev = if meta.file_type().is_block_device() {
+        meta.rdev()
+    } else {
+        meta.dev()
+    };
+    // `major` and `minor` are only safe in newer `libc` releases
+    #[allow(unused_unsafe)]
+    let (major, minor) = unsafe { (libc::major(dev), libc::minor(dev)) };
+    let sys = std::format!("/sys/dev/block/{}:{}", major, minor);
+
+    dm_crypt(Path::new(&sys), 0)
+}
+
+/// Whether the block device at `sys` is dm-crypt or stacked on dm-crypt.
+fn dm_crypt(sys: &Path, depth: usize) -> bool {
+    if depth > 8 {
+        return false;
+    }
+
+    if let Some(uuid) = read_trimmed(sys.join("dm/uuid")) {
+        if uuid.starts_with("CRYPT-") {
+            return true;
+        }
+    }
+
+    // device mapper targets list the devices they sit on
+    let slaves = match fs::read_dir(sys.join("slaves")) {
+        Ok(slaves) => slaves,
+        Err(_) => return false,
+    };
+    slaves
+        .filter_map(Result::ok)
+        .any(|slave| dm_crypt(&slave.path(), depth + 1))
+}